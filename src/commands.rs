@@ -1,8 +1,61 @@
-use std::{fs, path::Path};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
 
+use glob::Pattern;
+use kube::api::DynamicObject;
 use serde_json::Value;
 
-use crate::{diff::generate_diff, filter::filter_resource, kube_client::KubeClient, kustomize};
+use crate::{
+    diff::generate_diff,
+    enums::{ChangeStatus, Normalization},
+    field_diff::{align_lists, diff_values, project_onto, FieldChange, FieldChangeKind},
+    filter::{apply_ignore_rules, filter_resource},
+    helm::{self, HelmTarget},
+    kube_client::{is_forbidden, ApplyConflict, DryRunResult, KubeClient},
+    kustomize,
+    managed_fields::{apply_manager, owned_fields, retain_owned},
+    processor::DiffOptions,
+    secrets::mask_secret_data,
+    three_way::{classify_changes, Base},
+};
+
+/// Diff of a single resource as produced by [`Commands::get_diff`]
+#[derive(Debug, Clone, Default)]
+pub struct ResourceDiff {
+    /// Whether the resource is new, changed or unchanged
+    pub status: ChangeStatus,
+    /// Unified diff output, None if there are no changes
+    pub diff: Option<String>,
+    /// Fields that differ between the live and local resource.
+    ///
+    /// Empty for added and removed resources, where the whole manifest is the change.
+    pub field_changes: Vec<FieldChange>,
+    /// Fields owned by other field managers, only checked with `report_conflicts`
+    pub conflicts: Vec<ApplyConflict>,
+    /// How the local manifest was normalized before it was compared
+    pub normalization: Normalization,
+}
+
+/// Target name that reads manifests from stdin
+pub const STDIN_TARGET: &str = "-";
+
+/// File listing glob patterns of paths to skip when reading plain directories
+const IGNORE_FILE: &str = ".kubediffignore";
+
+/// Options controlling how manifests are built from a target path
+#[derive(Debug, Clone, Default)]
+pub struct BuildOptions {
+    /// Rendering settings for Helm chart targets
+    pub helm: Vec<HelmTarget>,
+    /// Descend into subdirectories of plain manifest directories
+    pub recursive: bool,
+    /// Only read files whose path relative to the target matches one of these globs
+    pub include: Vec<String>,
+    /// Skip files whose path relative to the target matches one of these globs
+    pub exclude: Vec<String>,
+}
 
 pub struct Commands;
 
@@ -12,7 +65,25 @@ impl Commands {
     /// Uses server-side dry-run apply to get the normalized local manifest with
     /// all server defaults applied, then compares it to the live resource.
     /// This matches kubectl diff behavior exactly.
-    pub async fn get_diff(client: &KubeClient, input: &str) -> anyhow::Result<String> {
+    pub async fn get_diff(client: &KubeClient, input: &str) -> anyhow::Result<ResourceDiff> {
+        Self::get_diff_with_options(client, input, &DiffOptions::default()).await
+    }
+
+    /// Get diff for a single Kubernetes resource, applying the given options.
+    ///
+    /// Configured ignore rules are applied to both the live and the dry-run
+    /// object after the built-in filters, and Secret values are masked unless
+    /// `show_secrets` is set. With `report_conflicts`, the dry-run doesn't
+    /// force field ownership and conflicts with other managers are reported.
+    ///
+    /// If the dry-run is forbidden, or with `client_side`, the live object is
+    /// instead projected onto the fields of the manifest, so defaults the
+    /// server would apply are neither shown nor compared.
+    pub async fn get_diff_with_options(
+        client: &KubeClient,
+        input: &str,
+        options: &DiffOptions,
+    ) -> anyhow::Result<ResourceDiff> {
         // Parse local YAML to JSON
         let local_value: Value = serde_yaml::from_str(input)?;
 
@@ -26,9 +97,17 @@ impl Commands {
         let resource_id = format!("{}/{}", kind, name);
 
         // Apply local manifest with dry-run to get server-normalized version
-        // This applies all server defaults, just like kubectl diff does
-        let dry_run_result = client.apply_dry_run(&local_value).await?;
-        let mut local_normalized: Value = serde_json::to_value(&dry_run_result)?;
+        // This applies all server defaults, just like kubectl diff does.
+        // Without permission to patch, fall back to comparing client-side
+        let dry_run = if options.client_side {
+            None
+        } else {
+            match Self::dry_run(client, &local_value, options).await {
+                Ok(result) => Some(result),
+                Err(e) if is_forbidden(&e) => None,
+                Err(e) => return Err(e),
+            }
+        };
 
         // Fetch live resource from cluster
         let api_version = local_value["apiVersion"]
@@ -39,43 +118,255 @@ impl Commands {
             .get_live_resource(api_version, kind, namespace, name)
             .await?;
 
-        match live {
-            None => {
-                // Resource doesn't exist in cluster - show as new
-                filter_resource(&mut local_normalized);
-                let local_yaml = serde_yaml::to_string(&local_normalized)?;
+        let live_value = live.map(|obj| serde_json::to_value(&obj)).transpose()?;
+        match dry_run {
+            Some((dry_run_result, conflicts)) => {
+                let local_normalized: Value = serde_json::to_value(&dry_run_result)?;
+                let mut resource_diff = Self::compare(
+                    &resource_id,
+                    live_value,
+                    Some(local_normalized),
+                    options,
+                    false,
+                )?;
+                resource_diff.normalization = Normalization::DryRun;
+                resource_diff.conflicts = conflicts;
+                Ok(resource_diff)
+            }
+            None => Self::diff_resources_client_side(
+                &resource_id,
+                live_value,
+                Some(local_value),
+                options,
+            ),
+        }
+    }
+
+    /// Server-side dry-run apply of a manifest.
+    ///
+    /// With `report_conflicts` the apply isn't forced at first but done as
+    /// the manager the manifests are deployed with, and the fields that
+    /// forcing it takes over from other managers are returned as well.
+    async fn dry_run(
+        client: &KubeClient,
+        manifest: &Value,
+        options: &DiffOptions,
+    ) -> anyhow::Result<(DynamicObject, Vec<ApplyConflict>)> {
+        if !options.report_conflicts {
+            return Ok((client.apply_dry_run(manifest).await?, vec![]));
+        }
+
+        let field_manager = apply_manager(&options.field_managers);
+        match client
+            .apply_dry_run_without_force(manifest, field_manager)
+            .await?
+        {
+            DryRunResult::Applied(object) => Ok((*object, vec![])),
+            // Still show what the apply would change once it takes the fields over
+            DryRunResult::Conflicts(conflicts) => Ok((
+                client.apply_dry_run_as(manifest, field_manager).await?,
+                conflicts,
+            )),
+        }
+    }
+
+    /// Get the diff of a live resource that no longer exists in the manifests.
+    ///
+    /// The live object is shown as removed, i.e. diffed against an empty document.
+    pub fn get_deletion_diff(
+        live: &DynamicObject,
+        options: &DiffOptions,
+    ) -> anyhow::Result<ResourceDiff> {
+        let kind = live
+            .types
+            .as_ref()
+            .map(|t| t.kind.as_str())
+            .ok_or_else(|| anyhow::anyhow!("Missing kind"))?;
+        let name = live
+            .metadata
+            .name
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("Missing metadata.name"))?;
+        let resource_id = format!("{}/{}", kind, name);
+
+        let live_value: Value = serde_json::to_value(live)?;
+        Self::diff_resources(&resource_id, Some(live_value), None, options)
+    }
+
+    /// Filter and diff two versions of a resource.
+    ///
+    /// `live` is the original and `local` the modified version. A missing
+    /// `live` reports the resource as added, a missing `local` as removed.
+    /// Both sides go through the built-in filters and ignore rules, and
+    /// Secret values are masked unless `show_secrets` is set. With
+    /// `three_way`, field changes are classified against the last applied
+    /// state read from the unfiltered `live` object. With `owned_only`, both
+    /// sides are restricted to the fields owned by our field managers.
+    pub fn diff_resources(
+        resource_id: &str,
+        live: Option<Value>,
+        local: Option<Value>,
+        options: &DiffOptions,
+    ) -> anyhow::Result<ResourceDiff> {
+        Self::compare(resource_id, live, local, options, false)
+    }
+
+    /// [`Commands::diff_resources`] for a `local` manifest that wasn't
+    /// normalized by the API server.
+    ///
+    /// The filtered `live` object is projected onto the fields of `local`,
+    /// so defaults set by the server don't show up as removed.
+    pub fn diff_resources_client_side(
+        resource_id: &str,
+        live: Option<Value>,
+        local: Option<Value>,
+        options: &DiffOptions,
+    ) -> anyhow::Result<ResourceDiff> {
+        let mut resource_diff = Self::compare(resource_id, live, local, options, true)?;
+        resource_diff.normalization = Normalization::ClientSide;
+        Ok(resource_diff)
+    }
 
-                Ok(generate_diff(&resource_id, "", &local_yaml).unwrap_or_default())
+    /// [`Commands::diff_resources`], optionally projecting the filtered live
+    /// object onto the fields of the local one for a client-side comparison.
+    fn compare(
+        resource_id: &str,
+        mut live: Option<Value>,
+        mut local: Option<Value>,
+        options: &DiffOptions,
+        project: bool,
+    ) -> anyhow::Result<ResourceDiff> {
+        // Read what was last applied and who owns which field before the filters remove it
+        let base = match &live {
+            Some(live_value) if options.three_way => Base::from_live(live_value),
+            _ => None,
+        };
+
+        let owned = if options.owned_only {
+            owned_fields(live.as_ref(), local.as_ref(), &options.field_managers)
+        } else {
+            None
+        };
+
+        // Apply filters to both (remove status, managedFields, etc.)
+        for value in live.iter_mut().chain(local.iter_mut()) {
+            filter_resource(value);
+            apply_ignore_rules(value, &options.ignore_rules)?;
+            if let Some(owned) = &owned {
+                retain_owned(value, owned);
             }
-            Some(live_obj) => {
-                // Compare normalized local with live
-                let mut live_value: Value = serde_json::to_value(&live_obj)?;
+        }
+        // Without server defaults, only fields written in the manifest can be compared
+        if let (true, Some(live_value), Some(local_value)) = (project, &mut live, &local) {
+            project_onto(live_value, local_value);
+        }
+        // Classification compares real values, keep them before Secrets are masked
+        let unmasked = base.as_ref().map(|_| (live.clone(), local.clone()));
+        if !options.show_secrets {
+            match (&mut live, &mut local) {
+                (live, Some(local)) => {
+                    mask_secret_data(live.as_mut(), local, options.secret_fingerprints)
+                }
+                // Masking a lone side marks every value as plain `***`
+                (Some(live), None) => mask_secret_data(None, live, options.secret_fingerprints),
+                (None, None) => {}
+            }
+        }
 
-                // Apply filters to both (remove status, managedFields, etc.)
-                filter_resource(&mut live_value);
-                filter_resource(&mut local_normalized);
+        match (live, local) {
+            (Some(mut live_value), Some(local_value)) => {
+                let mut field_changes = diff_values(&live_value, &local_value);
+                if let (Some(base), Some((Some(live), Some(local)))) = (&base, &unmasked) {
+                    classify_changes(
+                        &mut field_changes,
+                        base,
+                        live,
+                        local,
+                        &options.field_managers,
+                    );
+                }
+
+                // Match list elements by merge key so reorderings don't show as changes
+                align_lists(&mut live_value, &local_value);
 
                 // Convert to YAML for diff
                 let live_yaml = serde_yaml::to_string(&live_value)?;
-                let local_yaml = serde_yaml::to_string(&local_normalized)?;
+                let local_yaml = serde_yaml::to_string(&local_value)?;
 
-                Ok(generate_diff(&resource_id, &live_yaml, &local_yaml).unwrap_or_default())
+                let diff = generate_diff(resource_id, &live_yaml, &local_yaml);
+                let reordered = field_changes
+                    .iter()
+                    .any(|c| c.change == FieldChangeKind::Reordered);
+                let status = if diff.is_some() || reordered {
+                    ChangeStatus::Changed
+                } else {
+                    ChangeStatus::Unchanged
+                };
+
+                Ok(ResourceDiff {
+                    status,
+                    diff,
+                    field_changes,
+                    conflicts: vec![],
+                    normalization: Normalization::None,
+                })
+            }
+            (None, Some(local_value)) => {
+                // Resource doesn't exist yet - show as new
+                let local_yaml = serde_yaml::to_string(&local_value)?;
+                Ok(ResourceDiff {
+                    status: ChangeStatus::Added,
+                    diff: generate_diff(resource_id, "", &local_yaml),
+                    field_changes: vec![],
+                    conflicts: vec![],
+                    normalization: Normalization::None,
+                })
             }
+            (Some(live_value), None) => {
+                let live_yaml = serde_yaml::to_string(&live_value)?;
+                Ok(ResourceDiff {
+                    status: ChangeStatus::Removed,
+                    diff: generate_diff(resource_id, &live_yaml, ""),
+                    field_changes: vec![],
+                    conflicts: vec![],
+                    normalization: Normalization::None,
+                })
+            }
+            (None, None) => Ok(ResourceDiff::default()),
         }
     }
 
     /// Build Kubernetes manifests from a target path.
     ///
     /// Handles:
+    /// - Stdin, when the target is `-`
     /// - Single YAML files
+    /// - Helm chart directories (uses `helm template`)
     /// - Kustomize directories (uses embedded kustomize binary)
-    /// - Regular directories (concatenates all YAML files)
+    /// - Regular directories (concatenates all YAML and JSON files in sorted
+    ///   order, optionally recursing into subdirectories)
     pub fn get_build(target: &str) -> anyhow::Result<String> {
+        Self::get_build_with_options(target, &BuildOptions::default())
+    }
+
+    /// Build Kubernetes manifests from a target path with the given options.
+    pub fn get_build_with_options(target: &str, options: &BuildOptions) -> anyhow::Result<String> {
+        // Stdin - read everything piped in
+        if target == STDIN_TARGET {
+            return Ok(io::read_to_string(io::stdin())?);
+        }
+
         let path = Path::new(target);
 
         // Single file - just read it
         if path.is_file() {
-            return Ok(fs::read_to_string(path)?);
+            return read_manifest_file(path);
+        }
+
+        // Helm chart - render with the settings configured for this target
+        if helm::is_chart(target) {
+            let settings = options.helm.iter().find(|h| h.matches(target));
+            return helm::build(target, settings);
         }
 
         // Kustomize directory - use embedded kustomize
@@ -83,30 +374,173 @@ impl Commands {
             return kustomize::build(target);
         }
 
-        // Regular directory - concatenate all YAML files
-        let mut combined_output = String::new();
-        for entry in fs::read_dir(target)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.is_file() {
-                if let Some(ext) = path.extension() {
-                    if ext == "yaml" || ext == "yml" {
-                        let content = fs::read_to_string(&path)?;
-                        combined_output.push_str(&content);
-                        // Ensure documents are separated
-                        if !combined_output.ends_with('\n') {
-                            combined_output.push('\n');
-                        }
-                        combined_output.push_str("---\n");
-                    }
-                }
+        // Regular directory - concatenate all manifest files in sorted order
+        let include = compile_patterns(&options.include)?;
+        let exclude = compile_patterns(&options.exclude)?;
+        let mut files = vec![];
+        collect_manifest_files(path, options.recursive, &[], &mut files)?;
+
+        let mut documents = vec![];
+        for file in files {
+            let relative = file.strip_prefix(path).unwrap_or(&file);
+            let included = include.is_empty() || include.iter().any(|p| p.matches_path(relative));
+            let excluded = exclude.iter().any(|p| p.matches_path(relative));
+            if included && !excluded {
+                documents.push(read_manifest_file(&file)?);
             }
         }
 
-        Ok(combined_output)
+        Ok(join_documents(&documents))
     }
 }
 
+/// Read a manifest file, converting JSON manifests to YAML.
+///
+/// JSON that doesn't parse is kept as it is, YAML being a superset of JSON,
+/// so it is reported as a parse error of its document instead of failing
+/// the whole build.
+fn read_manifest_file(path: &Path) -> anyhow::Result<String> {
+    let content = fs::read_to_string(path)?;
+    if path.extension().is_some_and(|ext| ext == "json") {
+        if let Ok(value) = serde_json::from_str::<Value>(&content) {
+            return Ok(serde_yaml::to_string(&value)?);
+        }
+    }
+    Ok(content)
+}
+
+/// Join manifest files into a single multi-document YAML string
+fn join_documents(documents: &[String]) -> String {
+    let mut combined_output = String::new();
+    for content in documents {
+        // Ensure documents are separated
+        if !combined_output.is_empty() {
+            if !combined_output.ends_with('\n') {
+                combined_output.push('\n');
+            }
+            // Files may start with their own separator
+            if !content.starts_with("---") {
+                combined_output.push_str("---\n");
+            }
+        }
+        combined_output.push_str(content);
+    }
+    combined_output
+}
+
+fn compile_patterns(patterns: &[String]) -> anyhow::Result<Vec<Pattern>> {
+    patterns
+        .iter()
+        .map(|p| Pattern::new(p).map_err(|e| anyhow::anyhow!("Invalid glob '{}': {}", p, e)))
+        .collect()
+}
+
+/// A pattern read from a `.kubediffignore` file
+#[derive(Debug, Clone)]
+struct IgnorePattern {
+    /// Directory containing the ignore file
+    base: PathBuf,
+    pattern: Pattern,
+    /// Patterns containing a slash match the path relative to `base`,
+    /// others match the file name at any depth
+    anchored: bool,
+    /// Patterns ending with a slash only match directories
+    dir_only: bool,
+}
+
+impl IgnorePattern {
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let Ok(relative) = path.strip_prefix(&self.base) else {
+            return false;
+        };
+        if self.anchored {
+            self.pattern.matches_path(relative)
+        } else {
+            path.file_name()
+                .is_some_and(|name| self.pattern.matches(&name.to_string_lossy()))
+        }
+    }
+}
+
+/// Read the patterns of the `.kubediffignore` file in `dir`, if any.
+///
+/// Blank lines and lines starting with `#` are skipped.
+fn read_ignore_file(dir: &Path) -> anyhow::Result<Vec<IgnorePattern>> {
+    let ignore_file = dir.join(IGNORE_FILE);
+    if !ignore_file.is_file() {
+        return Ok(vec![]);
+    }
+
+    let mut patterns = vec![];
+    for line in fs::read_to_string(&ignore_file)?.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let dir_only = line.ends_with('/');
+        let line = line.trim_end_matches('/');
+        let anchored = line.contains('/');
+        let line = line.trim_start_matches('/');
+        let pattern = Pattern::new(line).map_err(|e| {
+            anyhow::anyhow!(
+                "Invalid pattern '{}' in {}: {}",
+                line,
+                ignore_file.display(),
+                e
+            )
+        })?;
+        patterns.push(IgnorePattern {
+            base: dir.to_path_buf(),
+            pattern,
+            anchored,
+            dir_only,
+        });
+    }
+    Ok(patterns)
+}
+
+/// Collect YAML and JSON manifest files below `dir` in sorted order.
+///
+/// Nested kustomize and Helm chart directories are skipped, as their files
+/// are build inputs rather than manifests.
+fn collect_manifest_files(
+    dir: &Path,
+    recursive: bool,
+    inherited_ignores: &[IgnorePattern],
+    files: &mut Vec<PathBuf>,
+) -> anyhow::Result<()> {
+    let mut ignores = inherited_ignores.to_vec();
+    ignores.extend(read_ignore_file(dir)?);
+
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    entries.sort();
+
+    for path in entries {
+        let is_dir = path.is_dir();
+        if ignores.iter().any(|i| i.matches(&path, is_dir)) {
+            continue;
+        }
+
+        if is_dir {
+            let nested = path.to_string_lossy();
+            if recursive && !helm::is_chart(&nested) && !is_kustomize_directory(&nested)? {
+                collect_manifest_files(&path, recursive, &ignores, files)?;
+            }
+        } else if path
+            .extension()
+            .is_some_and(|ext| ext == "yaml" || ext == "yml" || ext == "json")
+        {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
 fn is_kustomize_directory(target: &str) -> anyhow::Result<bool> {
     for entry in fs::read_dir(target)? {
         let entry = entry?;
@@ -120,3 +554,115 @@ fn is_kustomize_directory(target: &str) -> anyhow::Result<bool> {
     }
     Ok(false)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    /// Empty directory for a test, removed before it is returned
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("kubediff-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn joins_documents_with_separators() {
+        let documents = vec![
+            "a: 1".to_string(),
+            "b: 2\n".to_string(),
+            "---\nc: 3\n---\n".to_string(),
+            "d: 4\n".to_string(),
+        ];
+        assert_eq!(
+            join_documents(&documents),
+            "a: 1\n---\nb: 2\n---\nc: 3\n---\n---\nd: 4\n"
+        );
+        assert_eq!(join_documents(&[]), "");
+    }
+
+    #[test]
+    fn matches_ignore_patterns_like_gitignore() {
+        let dir = test_dir("ignore");
+        fs::write(
+            dir.join(IGNORE_FILE),
+            "# comment\n\n*.tmp.yaml\n/secrets/\nbase/values.yaml\n",
+        )
+        .unwrap();
+        let patterns = read_ignore_file(&dir).unwrap();
+        let ignored =
+            |path: &str, is_dir: bool| patterns.iter().any(|p| p.matches(&dir.join(path), is_dir));
+
+        // Unanchored patterns match the file name at any depth
+        assert!(ignored("a.tmp.yaml", false));
+        assert!(ignored("nested/b.tmp.yaml", false));
+        // Directory patterns only match directories
+        assert!(ignored("secrets", true));
+        assert!(!ignored("secrets", false));
+        // Anchored patterns match the path relative to the ignore file
+        assert!(ignored("base/values.yaml", false));
+        assert!(!ignored("other/base/values.yaml", false));
+        assert!(!ignored("deployment.yaml", false));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn collects_manifest_files_without_ignored_ones() {
+        let dir = test_dir("collect");
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        fs::write(dir.join(IGNORE_FILE), "skip.yaml\n").unwrap();
+        for file in ["b.yaml", "a.json", "skip.yaml", "notes.txt", "nested/c.yml"] {
+            fs::write(dir.join(file), "").unwrap();
+        }
+
+        let mut files = vec![];
+        collect_manifest_files(&dir, true, &[], &mut files).unwrap();
+        let names: Vec<String> = files
+            .iter()
+            .map(|f| f.strip_prefix(&dir).unwrap().display().to_string())
+            .collect();
+        assert_eq!(names, vec!["a.json", "b.yaml", "nested/c.yml"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn field_changes_of_secrets_hold_no_raw_values() {
+        let secret = |data: serde_json::Value| {
+            serde_json::json!({
+                "apiVersion": "v1",
+                "kind": "Secret",
+                "metadata": {"name": "s"},
+                "data": data,
+                "stringData": {"token": "plain-text"},
+            })
+        };
+        let live = secret(serde_json::json!({"password": "b2xk", "removed": "Z29uZQ=="}));
+        let local = secret(serde_json::json!({"password": "bmV3", "added": "YWRkZWQ="}));
+
+        for three_way in [false, true] {
+            let options = DiffOptions {
+                three_way,
+                ..DiffOptions::default()
+            };
+            let diff = Commands::diff_resources(
+                "Secret/s",
+                Some(live.clone()),
+                Some(local.clone()),
+                &options,
+            )
+            .unwrap();
+            assert!(!diff.field_changes.is_empty());
+            let serialized = serde_json::to_string(&diff.field_changes).unwrap();
+            let printed = diff.diff.unwrap();
+            for raw in ["b2xk", "bmV3", "Z29uZQ==", "YWRkZWQ=", "plain-text"] {
+                assert!(!serialized.contains(raw), "{} in field changes", raw);
+                assert!(!printed.contains(raw), "{} in diff", raw);
+            }
+        }
+    }
+}
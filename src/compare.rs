@@ -0,0 +1,398 @@
+//! Comparison of two sets of resources.
+//!
+//! Resources are paired by apiVersion, kind, namespace and name and diffed
+//! against each other instead of against a single cluster: rendered
+//! manifests offline, or the live objects of two clusters. Resources only
+//! present on one side are reported as added or removed.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use futures::future::join_all;
+use kube::api::DynamicObject;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{
+    commands::{Commands, STDIN_TARGET},
+    error::KubediffError,
+    git,
+    kube_client::KubeClient,
+    processor::{DiffOptions, DiffResult, TargetResult},
+    resource::{is_owned, ResourceId},
+    selector::ResourceSelector,
+};
+
+pub struct Compare;
+
+impl Compare {
+    /// Compare two multi-document YAML strings.
+    ///
+    /// `left` is the original and `right` the modified version, so the diff
+    /// reads like a change from `left` to `right`. If one side fails to
+    /// parse, resources only found on the other side are reported as
+    /// skipped, they may just be among the documents that weren't read.
+    pub fn manifests(
+        source_name: &str,
+        left: &str,
+        right: &str,
+        options: &DiffOptions,
+    ) -> TargetResult {
+        let mut results = vec![];
+        let namespace = options.offline_namespace();
+        let (left_docs, left_truncated) = parse_documents(
+            source_name,
+            left,
+            &options.selector,
+            namespace,
+            &mut results,
+        );
+        let (right_docs, right_truncated) = parse_documents(
+            source_name,
+            right,
+            &options.selector,
+            namespace,
+            &mut results,
+        );
+
+        let ids: BTreeSet<&ResourceId> = left_docs.keys().chain(right_docs.keys()).collect();
+        for id in ids {
+            let left_doc = left_docs.get(id).cloned();
+            let right_doc = right_docs.get(id).cloned();
+            // A resource may only be missing because its side stopped at a parse error
+            let skipped =
+                (left_doc.is_none() && left_truncated) || (right_doc.is_none() && right_truncated);
+            let diff = Commands::diff_resources(&id.to_string(), left_doc, right_doc, options);
+            let result = DiffResult::for_resource(source_name, id, diff);
+            results.push(if skipped { result.skipped() } else { result });
+        }
+
+        TargetResult {
+            target: source_name.to_string(),
+            results,
+            build_error: None,
+        }
+    }
+
+    /// Build and compare two manifest sources without cluster access.
+    ///
+    /// Each source can be anything [`Commands::get_build`] understands: a
+    /// file, a plain or kustomize directory, a Helm chart or `-` for stdin.
+    pub fn sources(left: &str, right: &str, options: &DiffOptions) -> TargetResult {
+        let source_name = format!("{} -> {}", left, right);
+        let build_error = |error: String| TargetResult {
+            target: source_name.clone(),
+            results: vec![],
+            build_error: Some(KubediffError::build_failed(error)),
+        };
+
+        if left == STDIN_TARGET && right == STDIN_TARGET {
+            return build_error("Only one side of a comparison can be read from stdin".to_string());
+        }
+
+        let left_build = match Commands::get_build_with_options(left, &options.build) {
+            Ok(build) => build,
+            Err(e) => return build_error(format!("Failed to build {}: {}", left, e)),
+        };
+        let right_build = match Commands::get_build_with_options(right, &options.build) {
+            Ok(build) => build,
+            Err(e) => return build_error(format!("Failed to build {}: {}", right, e)),
+        };
+
+        Self::manifests(&source_name, &left_build, &right_build, options)
+    }
+
+    /// Compare the rendered output of a target at two git revisions.
+    ///
+    /// Without a `head` revision the target is built from the working tree.
+    pub fn revisions(
+        target: &str,
+        base: &str,
+        head: Option<&str>,
+        options: &DiffOptions,
+    ) -> TargetResult {
+        let left = git::build_at_revision(target, base, &options.build)
+            .map_err(|e| format!("Failed to build {} at {}: {}", target, base, e));
+        let right = match head {
+            Some(head) => git::build_at_revision(target, head, &options.build)
+                .map_err(|e| format!("Failed to build {} at {}: {}", target, head, e)),
+            None => {
+                Commands::get_build_with_options(target, &options.build).map_err(|e| e.to_string())
+            }
+        };
+
+        match (left, right) {
+            (Ok(left), Ok(right)) => Self::manifests(target, &left, &right, options),
+            (Err(e), _) | (_, Err(e)) => TargetResult {
+                target: target.to_string(),
+                results: vec![],
+                build_error: Some(KubediffError::build_failed(e)),
+            },
+        }
+    }
+}
+
+impl Compare {
+    /// Compare the live objects of a target's resources in two clusters.
+    ///
+    /// The target is built to find out which resources to fetch; `left` is
+    /// the original and `right` the modified cluster. Resources without a
+    /// namespace are looked up in each client's default namespace.
+    pub async fn clusters(
+        left: &KubeClient,
+        right: &KubeClient,
+        target: &str,
+        options: &DiffOptions,
+    ) -> TargetResult {
+        let build = match Commands::get_build_with_options(target, &options.build) {
+            Ok(build) => build,
+            Err(e) => {
+                return TargetResult {
+                    target: target.to_string(),
+                    results: vec![],
+                    build_error: Some(KubediffError::build_failed(e)),
+                }
+            }
+        };
+
+        let mut results = vec![];
+        let (documents, _) = parse_documents(
+            target,
+            &build,
+            &options.selector,
+            left.default_namespace(),
+            &mut results,
+        );
+
+        let futures: Vec<_> = documents
+            .keys()
+            .map(|id| async move {
+                let (left_obj, right_obj) =
+                    futures::join!(fetch_live(left, id), fetch_live(right, id));
+                let diff = left_obj.and_then(|left_value| {
+                    let right_value = right_obj?;
+                    Commands::diff_resources(&id.to_string(), left_value, right_value, options)
+                });
+                DiffResult::for_resource(target, id, diff)
+            })
+            .collect();
+        results.extend(join_all(futures).await);
+
+        TargetResult {
+            target: target.to_string(),
+            results,
+            build_error: None,
+        }
+    }
+
+    /// Compare the live objects matching a label selector in two clusters.
+    ///
+    /// Objects owned by other objects (ReplicaSets, Pods, ...) are skipped.
+    /// Objects are paired by API group rather than version, so clusters
+    /// serving different preferred versions still line up.
+    pub async fn clusters_by_selector(
+        left: &KubeClient,
+        right: &KubeClient,
+        label_selector: &str,
+        options: &DiffOptions,
+    ) -> TargetResult {
+        let source_name = format!("selector: {}", label_selector);
+        let (left_objects, right_objects) = futures::join!(
+            left.list_resources(label_selector),
+            right.list_resources(label_selector)
+        );
+
+        let (left_objects, right_objects) = match (left_objects, right_objects) {
+            (Ok(l), Ok(r)) => (l, r),
+            (Err(e), _) | (_, Err(e)) => {
+                return TargetResult {
+                    target: source_name,
+                    results: vec![],
+                    build_error: Some(e.context("Failed to list resources").into()),
+                }
+            }
+        };
+
+        let left_by_key =
+            objects_by_key(&left_objects, &options.selector, left.default_namespace());
+        let right_by_key =
+            objects_by_key(&right_objects, &options.selector, right.default_namespace());
+        let keys: BTreeSet<&ObjectKey> = left_by_key.keys().chain(right_by_key.keys()).collect();
+
+        let results = keys
+            .into_iter()
+            .map(|key| {
+                let left_entry = left_by_key.get(key);
+                let right_entry = right_by_key.get(key);
+                let Some((id, _)) = left_entry.or(right_entry) else {
+                    unreachable!("key comes from one of both maps")
+                };
+                let diff = Commands::diff_resources(
+                    &id.to_string(),
+                    left_entry.map(|(_, v)| v.clone()),
+                    right_entry.map(|(_, v)| v.clone()),
+                    options,
+                );
+                DiffResult::for_resource(&source_name, id, diff)
+            })
+            .collect();
+
+        TargetResult {
+            target: source_name,
+            results,
+            build_error: None,
+        }
+    }
+}
+
+/// Group, kind, namespace and name of an object
+type ObjectKey = (String, String, Option<String>, String);
+
+/// Error of a document that isn't valid YAML
+fn parse_error(document: usize, error: &serde_yaml::Error) -> KubediffError {
+    let message = error.to_string();
+    // Scanner errors only mention their position in the message
+    let line = error
+        .location()
+        .map(|location| location.line())
+        .or_else(|| {
+            let (_, rest) = message.rsplit_once(" at line ")?;
+            rest.split(' ').next()?.parse().ok()
+        });
+    KubediffError::ParseError {
+        document,
+        line,
+        message,
+    }
+}
+
+/// Key the objects selected by `selector`, `default_namespace` applies to objects without one
+fn objects_by_key(
+    objects: &[DynamicObject],
+    selector: &ResourceSelector,
+    default_namespace: &str,
+) -> BTreeMap<ObjectKey, (ResourceId, Value)> {
+    objects
+        .iter()
+        .filter(|obj| !is_owned(obj))
+        .filter_map(|obj| {
+            let id = ResourceId::from_object(obj)?;
+            let value = serde_json::to_value(obj).ok()?;
+            if !selector.matches_manifest(&value, Some(default_namespace)) {
+                return None;
+            }
+            let key = (
+                id.group().to_string(),
+                id.kind.clone(),
+                id.namespace.clone(),
+                id.name.clone(),
+            );
+            Some((key, (id, value)))
+        })
+        .collect()
+}
+
+/// Fetch the live object of a resource as JSON, None if it doesn't exist
+pub(crate) async fn fetch_live(
+    client: &KubeClient,
+    id: &ResourceId,
+) -> anyhow::Result<Option<Value>> {
+    let live = client
+        .get_live_resource(&id.api_version, &id.kind, id.namespace.as_deref(), &id.name)
+        .await?;
+    Ok(live.map(serde_json::to_value).transpose()?)
+}
+
+/// Parse a multi-document YAML string into resources keyed by identity.
+///
+/// Documents that can't be parsed, lack an identity or are duplicated are
+/// reported as errors in `errors`. Empty documents and those not matched by
+/// `selector` are skipped, `default_namespace` applies to documents without
+/// a namespace when selecting them.
+///
+/// Parsing stops at the first document that fails, dropping every document
+/// after it. A syntax error drops every document of `yaml`, as the parser
+/// reads the whole stream before the first document is returned. The
+/// returned flag tells whether documents were dropped.
+pub(crate) fn parse_documents(
+    source_name: &str,
+    yaml: &str,
+    selector: &ResourceSelector,
+    default_namespace: &str,
+    errors: &mut Vec<DiffResult>,
+) -> (BTreeMap<ResourceId, Value>, bool) {
+    let mut documents = BTreeMap::new();
+    for (index, document) in serde_yaml::Deserializer::from_str(yaml).enumerate() {
+        let value = match Value::deserialize(document) {
+            Ok(Value::Null) => continue,
+            Ok(value) => value,
+            Err(e) => {
+                errors.push(DiffResult::failed(source_name, parse_error(index, &e)));
+                // The parser doesn't recover, it would repeat the error forever
+                return (documents, true);
+            }
+        };
+
+        match ResourceId::from_value(&value) {
+            Some(id) if documents.contains_key(&id) => {
+                let error = Err(KubediffError::InvalidManifest {
+                    message: format!("Duplicate resource {}", id),
+                }
+                .into());
+                errors.push(DiffResult::for_resource(source_name, &id, error));
+            }
+            Some(id) => {
+                if selector.matches_manifest(&value, Some(default_namespace)) {
+                    documents.insert(id, value);
+                }
+            }
+            None => errors.push(DiffResult::failed(
+                source_name,
+                KubediffError::InvalidManifest {
+                    message: "Missing apiVersion, kind or metadata.name".to_string(),
+                },
+            )),
+        }
+    }
+    (documents, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_empty_documents() {
+        let yaml = "---\napiVersion: v1\nkind: ConfigMap\nmetadata:\n  name: a\n---\n---\n";
+        let mut errors = vec![];
+        let (documents, truncated) = parse_documents(
+            "test",
+            yaml,
+            &ResourceSelector::default(),
+            "default",
+            &mut errors,
+        );
+        assert_eq!(documents.len(), 1);
+        assert!(!truncated);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn drops_every_document_on_a_syntax_error() {
+        let yaml = "apiVersion: v1\nkind: ConfigMap\nmetadata:\n  name: a\n---\nkey: [\n---\napiVersion: v1\nkind: ConfigMap\nmetadata:\n  name: b\n";
+        let mut errors = vec![];
+        let (documents, truncated) = parse_documents(
+            "test",
+            yaml,
+            &ResourceSelector::default(),
+            "default",
+            &mut errors,
+        );
+        assert!(documents.is_empty());
+        assert!(truncated);
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            errors[0].error,
+            Some(KubediffError::ParseError { line: Some(7), .. })
+        ));
+    }
+}
@@ -0,0 +1,260 @@
+//! API discovery with an on-disk cache.
+//!
+//! Running full discovery against clusters with many CRDs takes seconds, so
+//! the discovered resources are cached per API server in
+//! `~/.kube/cache/kubediff/discovery`, similar to kubectl's discovery cache.
+//! A cached index is used until its TTL expires. Lookups that miss refresh
+//! only the API group in question, so newly installed CRDs are still found.
+
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Mutex, RwLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use kube::{
+    discovery::{self, ApiCapabilities, ApiGroup, ApiResource, Discovery, Scope},
+    Client,
+};
+use serde::{Deserialize, Serialize};
+
+/// Where and for how long discovery results are cached
+#[derive(Debug, Clone)]
+pub struct DiscoveryCacheOptions {
+    /// Directory holding one cache file per API server, None disables caching
+    pub dir: Option<PathBuf>,
+    /// How long a cached index is used before running full discovery again
+    pub ttl: Duration,
+}
+
+impl Default for DiscoveryCacheOptions {
+    fn default() -> Self {
+        DiscoveryCacheOptions {
+            dir: dirs::home_dir().map(|home| home.join(".kube/cache/kubediff/discovery")),
+            ttl: Duration::from_secs(6 * 60 * 60),
+        }
+    }
+}
+
+/// A resource type served by the API server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DiscoveredResource {
+    #[serde(flatten)]
+    resource: ApiResource,
+    namespaced: bool,
+    verbs: Vec<String>,
+    /// Whether this is the version of the kind to use when listing the group
+    recommended: bool,
+}
+
+impl DiscoveredResource {
+    fn to_kube(&self) -> (ApiResource, ApiCapabilities) {
+        let capabilities = ApiCapabilities {
+            scope: if self.namespaced {
+                Scope::Namespaced
+            } else {
+                Scope::Cluster
+            },
+            subresources: vec![],
+            operations: self.verbs.clone(),
+        };
+        (self.resource.clone(), capabilities)
+    }
+}
+
+/// The resource types of one API group in all served versions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DiscoveredGroup {
+    name: String,
+    resources: Vec<DiscoveredResource>,
+}
+
+impl DiscoveredGroup {
+    fn from_kube(group: &ApiGroup) -> Self {
+        let recommended: Vec<ApiResource> = group
+            .recommended_resources()
+            .into_iter()
+            .map(|(ar, _)| ar)
+            .collect();
+        let resources = group
+            .versions()
+            .flat_map(|version| group.versioned_resources(version))
+            .map(|(ar, caps)| DiscoveredResource {
+                recommended: recommended.contains(&ar),
+                namespaced: caps.scope == Scope::Namespaced,
+                verbs: caps.operations,
+                resource: ar,
+            })
+            .collect();
+        DiscoveredGroup {
+            name: group.name().to_string(),
+            resources,
+        }
+    }
+}
+
+/// Contents of a cache file
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CacheFile {
+    /// API server the index was discovered from
+    cluster: String,
+    /// Seconds since the epoch when full discovery ran
+    discovered_at: u64,
+    groups: Vec<DiscoveredGroup>,
+}
+
+/// Resource types served by an API server
+pub(crate) struct ApiIndex {
+    cluster: String,
+    discovered_at: u64,
+    groups: RwLock<Vec<DiscoveredGroup>>,
+    /// Cache file to keep up to date, None if caching is disabled
+    cache_file: Option<PathBuf>,
+    /// Groups refreshed during this run, so repeated misses don't refetch them
+    refreshed: Mutex<HashSet<String>>,
+    /// Whether the index was fully discovered during this run
+    fresh: bool,
+}
+
+impl ApiIndex {
+    /// Load the cached index of an API server, running full discovery if
+    /// there is none or it expired
+    pub(crate) async fn load(
+        client: &Client,
+        cluster: &str,
+        options: &DiscoveryCacheOptions,
+    ) -> Result<Self> {
+        let cache_file = options
+            .dir
+            .as_ref()
+            .filter(|_| !options.ttl.is_zero())
+            .map(|dir| dir.join(format!("{}.json", cache_key(cluster))));
+
+        if let Some(cached) = cache_file.as_deref().and_then(read_cache) {
+            let age = now().saturating_sub(cached.discovered_at);
+            if cached.cluster == cluster && age < options.ttl.as_secs() {
+                return Ok(ApiIndex {
+                    cluster: cluster.to_string(),
+                    discovered_at: cached.discovered_at,
+                    groups: RwLock::new(cached.groups),
+                    cache_file,
+                    refreshed: Mutex::default(),
+                    fresh: false,
+                });
+            }
+        }
+
+        let discovery = Discovery::new(client.clone()).run().await?;
+        let index = ApiIndex {
+            cluster: cluster.to_string(),
+            discovered_at: now(),
+            groups: RwLock::new(discovery.groups().map(DiscoveredGroup::from_kube).collect()),
+            cache_file,
+            refreshed: Mutex::default(),
+            fresh: true,
+        };
+        index.save();
+        Ok(index)
+    }
+
+    /// Find a resource type by group, version and kind
+    pub(crate) fn find(
+        &self,
+        group: &str,
+        version: &str,
+        kind: &str,
+    ) -> Option<(ApiResource, ApiCapabilities)> {
+        let groups = self.groups.read().unwrap();
+        groups
+            .iter()
+            .filter(|g| g.name == group)
+            .flat_map(|g| g.resources.iter())
+            .find(|r| r.resource.version == version && r.resource.kind == kind)
+            .map(DiscoveredResource::to_kube)
+    }
+
+    /// Rediscover a single group after a lookup missed, returns whether it was refetched
+    ///
+    /// Each group is refreshed at most once per run, and not at all if the
+    /// whole index was just discovered.
+    pub(crate) async fn refresh_group(&self, client: &Client, group: &str) -> Result<bool> {
+        if self.fresh || !self.refreshed.lock().unwrap().insert(group.to_string()) {
+            return Ok(false);
+        }
+
+        let discovered = match discovery::group(client, group).await {
+            Ok(api_group) => Some(DiscoveredGroup::from_kube(&api_group)),
+            Err(kube::Error::Discovery(_)) => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        {
+            let mut groups = self.groups.write().unwrap();
+            groups.retain(|g| g.name != group);
+            groups.extend(discovered);
+        }
+        self.save();
+        Ok(true)
+    }
+
+    /// The recommended version of every resource type
+    pub(crate) fn recommended_resources(&self) -> Vec<(ApiResource, ApiCapabilities)> {
+        let groups = self.groups.read().unwrap();
+        groups
+            .iter()
+            .flat_map(|g| g.resources.iter())
+            .filter(|r| r.recommended)
+            .map(DiscoveredResource::to_kube)
+            .collect()
+    }
+
+    /// Write the index to its cache file, failures only cost the next run time
+    fn save(&self) {
+        let Some(path) = &self.cache_file else {
+            return;
+        };
+        let cache = CacheFile {
+            cluster: self.cluster.clone(),
+            discovered_at: self.discovered_at,
+            groups: self.groups.read().unwrap().clone(),
+        };
+        if let (Some(dir), Ok(json)) = (path.parent(), serde_json::to_string(&cache)) {
+            let _ = fs::create_dir_all(dir).and_then(|_| fs::write(path, json));
+        }
+    }
+}
+
+fn read_cache(path: &Path) -> Option<CacheFile> {
+    let content = fs::read_to_string(path).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// File name for an API server, e.g. `10.0.0.1_6443` for `https://10.0.0.1:6443/`
+fn cache_key(cluster: &str) -> String {
+    let host = cluster
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .trim_end_matches('/');
+    host.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
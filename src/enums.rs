@@ -7,3 +7,37 @@ pub enum LogLevel {
     #[default]
     Error,
 }
+
+/// Outcome of comparing a single resource against the cluster
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeStatus {
+    /// Local manifest matches the live resource
+    #[default]
+    Unchanged,
+    /// Local manifest differs from the live resource
+    Changed,
+    /// Resource does not exist in the cluster yet
+    Added,
+    /// Resource exists in the cluster but is no longer in the manifests
+    Removed,
+    /// Processing the resource failed, see the error
+    Error,
+    /// Not compared, the manifests stopped parsing before its document
+    Skipped,
+}
+
+/// How the local manifest was normalized before it was compared
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Normalization {
+    /// The manifest wasn't sent to the API server, e.g. in offline comparisons
+    #[default]
+    None,
+    /// Server-side dry-run apply, with defaults and validation applied
+    DryRun,
+    /// The live object was projected onto the manifest's fields, defaults not applied
+    ClientSide,
+    /// Not validated, the CRD or Namespace it depends on is only created by the same target
+    Unvalidated,
+}
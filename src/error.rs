@@ -0,0 +1,139 @@
+//! Typed errors of targets and resources.
+//!
+//! Failures are carried on [`crate::TargetResult`] and [`crate::DiffResult`]
+//! as a [`KubediffError`], so embedders can tell a denied request from a
+//! rejected manifest without parsing messages. `Display` gives the message
+//! shown by the CLI.
+
+use std::fmt;
+
+use kube::core::Status;
+use serde::Serialize;
+
+use crate::kube_client::{parse_conflicts, ApplyConflict};
+
+/// Why a target or resource couldn't be diffed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum KubediffError {
+    /// Rendering the target with kustomize or helm, or reading it, failed
+    BuildFailed { message: String },
+    /// A document of the rendered manifests isn't valid YAML
+    ParseError {
+        /// Index of the document within the rendered output, starting at 0
+        document: usize,
+        /// Line of the error within the rendered output, starting at 1
+        line: Option<usize>,
+        message: String,
+    },
+    /// A document lacks its identity or is duplicated
+    InvalidManifest { message: String },
+    /// The API server doesn't serve the resource type, e.g. its CRD isn't installed
+    UnknownResourceType { api_version: String, kind: String },
+    /// The API server denied the request (403)
+    Forbidden { message: String },
+    /// The API server rejected the manifest (400, 422)
+    ValidationFailed {
+        message: String,
+        causes: Vec<ValidationCause>,
+    },
+    /// Other field managers own fields of the manifest (409)
+    Conflict {
+        message: String,
+        conflicts: Vec<ApplyConflict>,
+    },
+    /// A resource the request depends on doesn't exist (404), e.g. its namespace
+    NotFound { message: String },
+    /// The request didn't complete within the request timeout
+    Timeout { message: String },
+    /// Any other error response of the API server
+    Api { code: u16, message: String },
+    /// Anything else, e.g. connection or serialization errors
+    Other { message: String },
+}
+
+/// A field the API server rejected, from the causes of its response
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationCause {
+    /// Path of the field, e.g. `spec.replicas`
+    pub field: String,
+    /// Machine-readable reason, e.g. `FieldValueInvalid`
+    pub reason: String,
+    pub message: String,
+}
+
+impl KubediffError {
+    /// A target that couldn't be rendered
+    pub(crate) fn build_failed(message: impl fmt::Display) -> Self {
+        KubediffError::BuildFailed {
+            message: message.to_string(),
+        }
+    }
+
+    /// Classify the error response of the API server
+    fn from_status(status: &Status, message: String) -> Self {
+        match status.code {
+            403 => KubediffError::Forbidden { message },
+            404 => KubediffError::NotFound { message },
+            409 => KubediffError::Conflict {
+                conflicts: parse_conflicts(status),
+                message,
+            },
+            400 | 422 => KubediffError::ValidationFailed {
+                causes: status
+                    .details
+                    .iter()
+                    .flat_map(|details| &details.causes)
+                    .map(|cause| ValidationCause {
+                        field: cause.field.clone(),
+                        reason: cause.reason.clone(),
+                        message: cause.message.clone(),
+                    })
+                    .collect(),
+                message,
+            },
+            code => KubediffError::Api { code, message },
+        }
+    }
+}
+
+impl From<anyhow::Error> for KubediffError {
+    fn from(error: anyhow::Error) -> Self {
+        if let Some(error) = error.downcast_ref::<KubediffError>() {
+            return error.clone();
+        }
+        // Keep the context of the error, it is what the CLI prints
+        let message = format!("{:#}", error);
+        match error.downcast_ref::<kube::Error>() {
+            Some(kube::Error::Api(status)) => KubediffError::from_status(status, message),
+            _ => KubediffError::Other { message },
+        }
+    }
+}
+
+impl fmt::Display for KubediffError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KubediffError::UnknownResourceType { api_version, kind } => {
+                write!(f, "Unknown resource type: {}/{}", api_version, kind)
+            }
+            KubediffError::BuildFailed { message }
+            | KubediffError::ParseError { message, .. }
+            | KubediffError::InvalidManifest { message }
+            | KubediffError::Forbidden { message }
+            | KubediffError::ValidationFailed { message, .. }
+            | KubediffError::Conflict { message, .. }
+            | KubediffError::NotFound { message }
+            | KubediffError::Timeout { message }
+            | KubediffError::Api { message, .. }
+            | KubediffError::Other { message } => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for KubediffError {}
@@ -0,0 +1,653 @@
+//! Structural diff of Kubernetes resources.
+//!
+//! Where [`crate::diff`] compares YAML text line by line, this module walks
+//! the JSON values of the live and local resource and reports every field
+//! that was added, removed or changed along with its path, e.g.
+//! `spec.template.spec.containers[name=app].image`.
+//!
+//! Lists that Kubernetes treats as maps (containers, env, ports, volumes, ...)
+//! are matched element by element using their strategic merge patch merge
+//! keys, so reordering them is not reported as a change of every element.
+
+use std::fmt;
+
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+
+/// Strategic merge patch merge keys of well-known list fields.
+///
+/// The first key must be present on every element for a set of keys to be
+/// used, the remaining keys are included when present. A field may have
+/// several candidate sets, e.g. container ports and Service ports.
+const MERGE_KEYS: &[(&str, &[&str])] = &[
+    ("containers", &["name"]),
+    ("initContainers", &["name"]),
+    ("ephemeralContainers", &["name"]),
+    ("env", &["name"]),
+    ("volumes", &["name"]),
+    ("volumeMounts", &["mountPath"]),
+    ("volumeDevices", &["devicePath"]),
+    ("imagePullSecrets", &["name"]),
+    ("ports", &["containerPort", "protocol"]),
+    ("ports", &["port", "protocol"]),
+    ("hostAliases", &["ip"]),
+    (
+        "topologySpreadConstraints",
+        &["topologyKey", "whenUnsatisfiable"],
+    ),
+    ("resourceClaims", &["name"]),
+    ("conditions", &["type"]),
+];
+
+/// Keyed lists whose element order matters at runtime.
+///
+/// Init containers run in order and environment variables may reference the
+/// ones defined before them, so these lists are never aligned for the text diff.
+const ORDERED_LISTS: &[&str] = &["initContainers", "env"];
+
+/// A single step of a [`FieldPath`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum PathSegment {
+    /// Object field
+    Field(String),
+    /// List element matched by position
+    Index(usize),
+    /// List element matched by the value of its key fields, e.g. `name=app`
+    Key(Vec<(String, String)>),
+}
+
+/// Location of a field within a resource
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FieldPath(pub Vec<PathSegment>);
+
+impl FieldPath {
+    pub(crate) fn child(&self, segment: PathSegment) -> FieldPath {
+        let mut segments = self.0.clone();
+        segments.push(segment);
+        FieldPath(segments)
+    }
+
+    /// Look up the value at this path, None if any step doesn't exist
+    pub fn get<'a>(&self, value: &'a Value) -> Option<&'a Value> {
+        self.0
+            .iter()
+            .try_fold(value, |current, segment| match segment {
+                PathSegment::Field(name) => current.get(name),
+                PathSegment::Index(index) => current.get(index),
+                PathSegment::Key(pairs) => current.as_array()?.iter().find(|element| {
+                    pairs.iter().all(|(k, v)| {
+                        element.get(k).map(scalar_string).as_deref() == Some(v.as_str())
+                    })
+                }),
+            })
+    }
+
+    /// Whether `other` equals this path or lies within it
+    ///
+    /// Key segments match regardless of the order of their key fields.
+    pub fn covers(&self, other: &FieldPath) -> bool {
+        self.0.len() <= other.0.len()
+            && self
+                .0
+                .iter()
+                .zip(other.0.iter())
+                .all(|(a, b)| match (a, b) {
+                    (PathSegment::Key(a), PathSegment::Key(b)) => {
+                        let mut a = a.clone();
+                        let mut b = b.clone();
+                        a.sort();
+                        b.sort();
+                        a == b
+                    }
+                    _ => a == b,
+                })
+    }
+}
+
+impl fmt::Display for FieldPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, segment) in self.0.iter().enumerate() {
+            match segment {
+                PathSegment::Field(name) if is_plain_field(name) => {
+                    if i > 0 {
+                        write!(f, ".")?;
+                    }
+                    write!(f, "{}", name)?;
+                }
+                PathSegment::Field(name) => write!(f, "[{:?}]", name)?,
+                PathSegment::Index(index) => write!(f, "[{}]", index)?,
+                PathSegment::Key(pairs) => {
+                    let pairs: Vec<String> =
+                        pairs.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+                    write!(f, "[{}]", pairs.join(","))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for FieldPath {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Field names that can be written without brackets, i.e. not `app.kubernetes.io/name`
+fn is_plain_field(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// How a field differs between the live and the local resource
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldChangeKind {
+    /// Field only exists in the local manifest
+    Added,
+    /// Field only exists in the live resource
+    Removed,
+    /// Field exists on both sides with different values
+    Changed,
+    /// List holds the same elements on both sides in a different order
+    Reordered,
+}
+
+/// Why a field differs, as classified by a three-way diff
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeOrigin {
+    /// The manifest changed since it was last applied
+    Local,
+    /// The live object was changed outside of the manifests
+    Drift,
+    /// Both the manifest and the live object changed
+    Conflict,
+}
+
+/// A field that differs between the live and the local resource
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldChange {
+    /// Location of the field
+    pub path: FieldPath,
+    /// Whether the field was added, removed or changed
+    pub change: FieldChangeKind,
+    /// Live value, None if the field was added.
+    ///
+    /// For reordered lists this holds the element keys in live order.
+    pub old: Option<Value>,
+    /// Local value, None if the field was removed.
+    ///
+    /// For reordered lists this holds the element keys in local order.
+    pub new: Option<Value>,
+    /// Where the change comes from, only set by a three-way diff
+    pub origin: Option<ChangeOrigin>,
+}
+
+/// Compare live (original) and local (modified) values field by field.
+///
+/// Lists with known merge keys are matched by key so that changes are
+/// reported as `containers[name=app].image` instead of by position, other
+/// lists are compared by position. A pure change of order is reported as a
+/// single [`FieldChangeKind::Reordered`] change.
+pub fn diff_values(live: &Value, local: &Value) -> Vec<FieldChange> {
+    let mut changes = vec![];
+    walk(&FieldPath::default(), live, local, &mut changes);
+    changes
+}
+
+fn walk(path: &FieldPath, live: &Value, local: &Value, changes: &mut Vec<FieldChange>) {
+    match (live, local) {
+        (Value::Object(live_obj), Value::Object(local_obj)) => {
+            for (key, live_child) in live_obj {
+                let child_path = path.child(PathSegment::Field(key.clone()));
+                match local_obj.get(key) {
+                    Some(local_child) => walk(&child_path, live_child, local_child, changes),
+                    None => changes.push(removed(child_path, live_child)),
+                }
+            }
+            for (key, local_child) in local_obj {
+                if !live_obj.contains_key(key) {
+                    let child_path = path.child(PathSegment::Field(key.clone()));
+                    changes.push(added(child_path, local_child));
+                }
+            }
+        }
+        (Value::Array(live_arr), Value::Array(local_arr)) => {
+            let field = last_field(path);
+            match (
+                keyed_elements(live_arr, field),
+                keyed_elements(local_arr, field),
+            ) {
+                (Some(live_keyed), Some(local_keyed)) => {
+                    if let Some(change) = reordering(path, &live_keyed, &local_keyed) {
+                        changes.push(change);
+                    }
+                    for (key, live_child) in &live_keyed {
+                        let child_path = path.child(PathSegment::Key(key.clone()));
+                        match local_keyed.iter().find(|(k, _)| k == key) {
+                            Some((_, local_child)) => {
+                                walk(&child_path, live_child, local_child, changes)
+                            }
+                            None => changes.push(removed(child_path, live_child)),
+                        }
+                    }
+                    for (key, local_child) in &local_keyed {
+                        if !live_keyed.iter().any(|(k, _)| k == key) {
+                            let child_path = path.child(PathSegment::Key(key.clone()));
+                            changes.push(added(child_path, local_child));
+                        }
+                    }
+                }
+                _ => {
+                    for (i, live_child) in live_arr.iter().enumerate() {
+                        let child_path = path.child(PathSegment::Index(i));
+                        match local_arr.get(i) {
+                            Some(local_child) => {
+                                walk(&child_path, live_child, local_child, changes)
+                            }
+                            None => changes.push(removed(child_path, live_child)),
+                        }
+                    }
+                    for (i, local_child) in local_arr.iter().enumerate().skip(live_arr.len()) {
+                        let child_path = path.child(PathSegment::Index(i));
+                        changes.push(added(child_path, local_child));
+                    }
+                }
+            }
+        }
+        _ if live != local => changes.push(FieldChange {
+            path: path.clone(),
+            change: FieldChangeKind::Changed,
+            old: Some(live.clone()),
+            new: Some(local.clone()),
+            origin: None,
+        }),
+        _ => {}
+    }
+}
+
+/// List elements paired with the key fields identifying them
+type KeyedElements<'a> = Vec<(Vec<(String, String)>, &'a Value)>;
+
+/// Reorder keyed lists in `live` to follow the element order in `local`.
+///
+/// Elements that only exist in the live list keep their relative order and
+/// are moved to the end. Applied before generating the text diff so that a
+/// pure reordering does not show up as changed lines. Lists whose order
+/// matters at runtime, like `initContainers`, are left as they are.
+pub fn align_lists(live: &mut Value, local: &Value) {
+    align(&FieldPath::default(), live, local);
+}
+
+fn align(path: &FieldPath, live: &mut Value, local: &Value) {
+    match (live, local) {
+        (Value::Object(live_obj), Value::Object(local_obj)) => {
+            for (key, live_child) in live_obj.iter_mut() {
+                if let Some(local_child) = local_obj.get(key) {
+                    let child_path = path.child(PathSegment::Field(key.clone()));
+                    align(&child_path, live_child, local_child);
+                }
+            }
+        }
+        (Value::Array(live_arr), Value::Array(local_arr)) => {
+            let field = last_field(path);
+            let ordered = field.is_some_and(|field| ORDERED_LISTS.contains(&field));
+
+            // Pair every live element (by index) with its local counterpart
+            let pairs: Vec<(usize, Option<&Value>)> = match (
+                keyed_elements(live_arr, field),
+                keyed_elements(local_arr, field),
+            ) {
+                (Some(live_keyed), Some(local_keyed)) if !ordered => {
+                    let mut pairs: Vec<(usize, Option<&Value>)> = local_keyed
+                        .iter()
+                        .filter_map(|(key, local_child)| {
+                            let i = live_keyed.iter().position(|(k, _)| k == key)?;
+                            Some((i, Some(*local_child)))
+                        })
+                        .collect();
+                    let matched: Vec<usize> = pairs.iter().map(|(i, _)| *i).collect();
+                    pairs.extend(
+                        (0..live_keyed.len())
+                            .filter(|i| !matched.contains(i))
+                            .map(|i| (i, None)),
+                    );
+                    pairs
+                }
+                _ => (0..live_arr.len()).map(|i| (i, local_arr.get(i))).collect(),
+            };
+
+            let elements = std::mem::take(live_arr);
+            *live_arr = pairs
+                .into_iter()
+                .map(|(i, local_child)| {
+                    let mut child = elements[i].clone();
+                    if let Some(local_child) = local_child {
+                        align(&path.child(PathSegment::Index(i)), &mut child, local_child);
+                    }
+                    child
+                })
+                .collect();
+        }
+        _ => {}
+    }
+}
+
+/// Remove everything from `live` that doesn't exist in `local`.
+///
+/// Used to compare without the server's defaults: only the fields written
+/// in the manifest are kept on the live side. Keyed lists keep the elements
+/// whose key exists locally, other lists are projected element by element.
+pub fn project_onto(live: &mut Value, local: &Value) {
+    project(&FieldPath::default(), live, local);
+}
+
+fn project(path: &FieldPath, live: &mut Value, local: &Value) {
+    match (live, local) {
+        (Value::Object(live_obj), Value::Object(local_obj)) => {
+            live_obj.retain(|key, _| local_obj.contains_key(key));
+            for (key, live_child) in live_obj.iter_mut() {
+                let child_path = path.child(PathSegment::Field(key.clone()));
+                project(&child_path, live_child, &local_obj[key]);
+            }
+        }
+        (Value::Array(live_arr), Value::Array(local_arr)) => {
+            let field = last_field(path);
+            let live_keys: Option<Vec<Vec<(String, String)>>> = keyed_elements(live_arr, field)
+                .map(|keyed| keyed.into_iter().map(|(key, _)| key).collect());
+
+            match (live_keys, keyed_elements(local_arr, field)) {
+                (Some(live_keys), Some(local_keyed)) => {
+                    let mut keys = live_keys.into_iter();
+                    live_arr.retain_mut(|live_child| {
+                        let key = keys.next().unwrap_or_default();
+                        match local_keyed.iter().find(|(k, _)| *k == key) {
+                            Some((_, local_child)) => {
+                                let child_path = path.child(PathSegment::Key(key));
+                                project(&child_path, live_child, local_child);
+                                true
+                            }
+                            None => false,
+                        }
+                    });
+                }
+                _ => {
+                    for (i, (live_child, local_child)) in
+                        live_arr.iter_mut().zip(local_arr.iter()).enumerate()
+                    {
+                        project(&path.child(PathSegment::Index(i)), live_child, local_child);
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Name of the field holding the value at `path`, if it is an object field
+fn last_field(path: &FieldPath) -> Option<&str> {
+    match path.0.last() {
+        Some(PathSegment::Field(name)) => Some(name),
+        _ => None,
+    }
+}
+
+/// Key list elements by their merge keys, None unless the list has known
+/// merge keys and every element has a unique key.
+fn keyed_elements<'a>(elements: &'a [Value], field: Option<&str>) -> Option<KeyedElements<'a>> {
+    MERGE_KEYS
+        .iter()
+        .filter(|(name, _)| Some(*name) == field)
+        .find_map(|(_, keys)| keyed_by(elements, keys))
+}
+
+fn keyed_by<'a>(elements: &'a [Value], keys: &[&str]) -> Option<KeyedElements<'a>> {
+    let mut keyed: KeyedElements = vec![];
+    for element in elements {
+        element.get(keys[0])?;
+        let key: Vec<(String, String)> = keys
+            .iter()
+            .filter_map(|k| element.get(*k).map(|v| (k.to_string(), scalar_string(v))))
+            .collect();
+        if keyed.iter().any(|(k, _)| *k == key) {
+            return None;
+        }
+        keyed.push((key, element));
+    }
+    Some(keyed)
+}
+
+pub(crate) fn scalar_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// A [`FieldChangeKind::Reordered`] change if both lists share their keys in a different order
+fn reordering(
+    path: &FieldPath,
+    live: &KeyedElements,
+    local: &KeyedElements,
+) -> Option<FieldChange> {
+    let live_keys: Vec<&Vec<(String, String)>> = live.iter().map(|(k, _)| k).collect();
+    let local_keys: Vec<&Vec<(String, String)>> = local
+        .iter()
+        .map(|(k, _)| k)
+        .filter(|k| live_keys.contains(k))
+        .collect();
+    let live_common: Vec<&Vec<(String, String)>> = live_keys
+        .iter()
+        .copied()
+        .filter(|k| local_keys.contains(k))
+        .collect();
+
+    if live_common == local_keys {
+        return None;
+    }
+
+    let format_keys = |keys: &[&Vec<(String, String)>]| {
+        Value::Array(
+            keys.iter()
+                .map(|key| {
+                    Value::String(FieldPath(vec![PathSegment::Key((*key).clone())]).to_string())
+                })
+                .collect(),
+        )
+    };
+
+    Some(FieldChange {
+        path: path.clone(),
+        change: FieldChangeKind::Reordered,
+        old: Some(format_keys(&live_common)),
+        new: Some(format_keys(&local_keys)),
+        origin: None,
+    })
+}
+
+fn added(path: FieldPath, value: &Value) -> FieldChange {
+    FieldChange {
+        path,
+        change: FieldChangeKind::Added,
+        old: None,
+        new: Some(value.clone()),
+        origin: None,
+    }
+}
+
+fn removed(path: FieldPath, value: &Value) -> FieldChange {
+    FieldChange {
+        path,
+        change: FieldChangeKind::Removed,
+        old: Some(value.clone()),
+        new: None,
+        origin: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn paths(changes: &[FieldChange]) -> Vec<String> {
+        changes.iter().map(|c| c.path.to_string()).collect()
+    }
+
+    #[test]
+    fn reports_added_removed_and_changed_fields() {
+        let live = json!({"spec": {"replicas": 1, "paused": false}});
+        let local = json!({"spec": {"replicas": 2, "minReadySeconds": 5}});
+
+        let changes = diff_values(&live, &local);
+        assert_eq!(
+            paths(&changes),
+            vec!["spec.paused", "spec.replicas", "spec.minReadySeconds"]
+        );
+        assert_eq!(changes[0].change, FieldChangeKind::Removed);
+        assert_eq!(changes[1].change, FieldChangeKind::Changed);
+        assert_eq!(changes[1].old, Some(json!(1)));
+        assert_eq!(changes[1].new, Some(json!(2)));
+        assert_eq!(changes[2].change, FieldChangeKind::Added);
+    }
+
+    #[test]
+    fn equal_values_have_no_changes() {
+        let value = json!({"metadata": {"labels": {"app": "web"}}, "data": [1, 2]});
+        assert!(diff_values(&value, &value).is_empty());
+    }
+
+    #[test]
+    fn formats_paths_with_brackets_for_special_keys() {
+        let live = json!({"metadata": {"labels": {"app.kubernetes.io/name": "a"}}});
+        let local = json!({"metadata": {"labels": {"app.kubernetes.io/name": "b"}}});
+        assert_eq!(
+            paths(&diff_values(&live, &local)),
+            vec![r#"metadata.labels["app.kubernetes.io/name"]"#]
+        );
+    }
+
+    #[test]
+    fn compares_unkeyed_lists_by_position() {
+        let live = json!({"args": ["a", "b"]});
+        let local = json!({"args": ["a", "c", "d"]});
+
+        let changes = diff_values(&live, &local);
+        assert_eq!(paths(&changes), vec!["args[1]", "args[2]"]);
+        assert_eq!(changes[1].change, FieldChangeKind::Added);
+    }
+
+    #[test]
+    fn matches_elements_by_merge_key() {
+        let live = json!({"containers": [
+            {"name": "app", "image": "app:1"},
+            {"name": "sidecar", "image": "sidecar:1"},
+        ]});
+        let local = json!({"containers": [
+            {"name": "sidecar", "image": "sidecar:1"},
+            {"name": "app", "image": "app:2"},
+        ]});
+
+        let changes = diff_values(&live, &local);
+        assert_eq!(
+            paths(&changes),
+            vec!["containers", "containers[name=app].image"]
+        );
+        assert_eq!(changes[0].change, FieldChangeKind::Reordered);
+        assert_eq!(
+            changes[0].new,
+            Some(json!(["[name=sidecar]", "[name=app]"]))
+        );
+    }
+
+    #[test]
+    fn matches_ports_by_compound_key() {
+        let live = json!({"ports": [{"port": 80, "protocol": "TCP", "name": "http"}]});
+        let local = json!({"ports": [{"port": 80, "protocol": "TCP", "name": "web"}]});
+        assert_eq!(
+            paths(&diff_values(&live, &local)),
+            vec!["ports[port=80,protocol=TCP].name"]
+        );
+    }
+
+    #[test]
+    fn lists_without_merge_keys_are_matched_by_position() {
+        let live = json!({"rules": [{"name": "a"}, {"name": "b"}]});
+        let local = json!({"rules": [{"name": "b"}, {"name": "a"}]});
+        assert_eq!(
+            paths(&diff_values(&live, &local)),
+            vec!["rules[0].name", "rules[1].name"]
+        );
+    }
+
+    #[test]
+    fn aligns_keyed_lists_to_the_local_order() {
+        let mut live = json!({"volumes": [{"name": "a"}, {"name": "b"}, {"name": "c"}]});
+        let local = json!({"volumes": [{"name": "b"}, {"name": "a"}]});
+
+        align_lists(&mut live, &local);
+        assert_eq!(
+            live,
+            json!({"volumes": [{"name": "b"}, {"name": "a"}, {"name": "c"}]})
+        );
+    }
+
+    #[test]
+    fn keeps_the_order_of_init_containers() {
+        let original = json!({"initContainers": [{"name": "migrate"}, {"name": "seed"}]});
+        let local = json!({"initContainers": [{"name": "seed"}, {"name": "migrate"}]});
+
+        let mut live = original.clone();
+        align_lists(&mut live, &local);
+        assert_eq!(live, original);
+        assert_eq!(
+            diff_values(&original, &local)[0].change,
+            FieldChangeKind::Reordered
+        );
+    }
+
+    fn field_path(fields: &[&str]) -> FieldPath {
+        FieldPath(
+            fields
+                .iter()
+                .map(|f| PathSegment::Field(f.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn paths_cover_themselves_and_their_children() {
+        let spec = field_path(&["spec"]);
+        let replicas = field_path(&["spec", "replicas"]);
+
+        assert!(replicas.covers(&replicas));
+        assert!(spec.covers(&replicas));
+        assert!(!replicas.covers(&spec));
+        assert!(!replicas.covers(&field_path(&["spec", "paused"])));
+    }
+
+    #[test]
+    fn key_segments_cover_regardless_of_field_order() {
+        let key = |pairs: &[(&str, &str)]| {
+            PathSegment::Key(
+                pairs
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+            )
+        };
+        let owned =
+            field_path(&["ports"]).child(key(&[("containerPort", "80"), ("protocol", "TCP")]));
+        let changed = field_path(&["ports"])
+            .child(key(&[("protocol", "TCP"), ("containerPort", "80")]))
+            .child(PathSegment::Field("name".to_string()));
+
+        assert!(owned.covers(&changed));
+        assert!(!changed.covers(&owned));
+    }
+}
@@ -1,8 +1,11 @@
 //! Field filtering for Kubernetes resources before diffing.
 //!
 //! This module removes server-managed fields from Kubernetes resources
-//! so that diffs only show meaningful changes.
+//! so that diffs only show meaningful changes, along with any fields the
+//! user configured to be ignored.
 
+use glob::Pattern;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 /// Annotations to remove from resources before diffing
@@ -54,3 +57,419 @@ pub fn filter_resource(value: &mut Value) {
         spec.remove("caBundle");
     }
 }
+
+/// User-configured fields to strip before diffing.
+///
+/// A rule without `apiVersion`, `kind` and `name` applies to every resource.
+/// Paths are either JSON Pointers (`/spec/replicas`) or JSONPath expressions
+/// (`$.spec.template.spec.containers[*].image`, `$.spec.containers[?(@.name=='app')].env`).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IgnoreRule {
+    /// Only apply to resources with this apiVersion
+    #[serde(
+        rename = "apiVersion",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
+    pub api_version: Option<String>,
+    /// Only apply to resources of this kind
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub kind: Option<String>,
+    /// Only apply to resources whose name matches this glob pattern
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub name: Option<String>,
+    /// Fields to remove
+    #[serde(default)]
+    pub paths: Vec<String>,
+}
+
+impl IgnoreRule {
+    /// Whether the rule applies to a resource with the given identifiers
+    pub fn matches(&self, api_version: &str, kind: &str, name: &str) -> bool {
+        self.api_version.as_deref().is_none_or(|v| v == api_version)
+            && self.kind.as_deref().is_none_or(|k| k == kind)
+            && self.name.as_deref().is_none_or(|pattern| {
+                Pattern::new(pattern).is_ok_and(|pattern| pattern.matches(name))
+            })
+    }
+}
+
+/// A single step of a parsed field path
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    /// Object key, or array index when applied to an array (JSON Pointer tokens)
+    Field(String),
+    /// Array index
+    Index(usize),
+    /// Every member of an object or element of an array
+    Wildcard,
+    /// Array elements whose `key` field equals `value`
+    Filter { key: String, value: String },
+}
+
+/// Remove the fields selected by all matching ignore rules from a resource.
+///
+/// The rules are matched against the apiVersion, kind and name of `value` itself.
+pub fn apply_ignore_rules(value: &mut Value, rules: &[IgnoreRule]) -> anyhow::Result<()> {
+    let api_version = value["apiVersion"].as_str().unwrap_or_default().to_string();
+    let kind = value["kind"].as_str().unwrap_or_default().to_string();
+    let name = value["metadata"]["name"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string();
+
+    for rule in rules {
+        if !rule.matches(&api_version, &kind, &name) {
+            continue;
+        }
+        for path in &rule.paths {
+            let segments = parse_path(path)?;
+            remove_path(value, &segments);
+        }
+    }
+    Ok(())
+}
+
+fn parse_path(path: &str) -> anyhow::Result<Vec<Segment>> {
+    if path.starts_with('/') {
+        Ok(parse_json_pointer(path))
+    } else if path.starts_with('$') {
+        parse_json_path(path)
+    } else {
+        Err(anyhow::anyhow!(
+            "Invalid ignore path '{}': expected a JSON Pointer (/a/b) or JSONPath ($.a.b)",
+            path
+        ))
+    }
+}
+
+fn parse_json_pointer(pointer: &str) -> Vec<Segment> {
+    pointer
+        .split('/')
+        .skip(1)
+        .map(|token| Segment::Field(token.replace("~1", "/").replace("~0", "~")))
+        .collect()
+}
+
+fn parse_json_path(path: &str) -> anyhow::Result<Vec<Segment>> {
+    let invalid = || anyhow::anyhow!("Invalid JSONPath '{}'", path);
+    let chars: Vec<char> = path.chars().collect();
+    let mut segments = vec![];
+    let mut i = 1;
+
+    while i < chars.len() {
+        match chars[i] {
+            '.' => {
+                let start = i + 1;
+                i = start;
+                while i < chars.len() && chars[i] != '.' && chars[i] != '[' {
+                    i += 1;
+                }
+                let key: String = chars[start..i].iter().collect();
+                match key.as_str() {
+                    "" => return Err(invalid()),
+                    "*" => segments.push(Segment::Wildcard),
+                    _ => segments.push(Segment::Field(key)),
+                }
+            }
+            '[' => {
+                let end = find_closing_bracket(&chars, i).ok_or_else(invalid)?;
+                let inner: String = chars[i + 1..end].iter().collect();
+                segments.push(parse_bracket(inner.trim()).ok_or_else(invalid)?);
+                i = end + 1;
+            }
+            _ => return Err(invalid()),
+        }
+    }
+    Ok(segments)
+}
+
+/// Find the `]` closing the bracket at `start`, skipping over quoted strings
+fn find_closing_bracket(chars: &[char], start: usize) -> Option<usize> {
+    let mut quote: Option<char> = None;
+    for (i, c) in chars.iter().enumerate().skip(start + 1) {
+        match (quote, c) {
+            (Some(q), c) if *c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(*c),
+            (None, ']') => return Some(i),
+            (None, _) => {}
+        }
+    }
+    None
+}
+
+fn parse_bracket(inner: &str) -> Option<Segment> {
+    if inner == "*" {
+        return Some(Segment::Wildcard);
+    }
+    if let Some(key) = unquote(inner) {
+        return Some(Segment::Field(key));
+    }
+    if let Ok(index) = inner.parse::<usize>() {
+        return Some(Segment::Index(index));
+    }
+
+    // Equality filter: ?(@.key=='value')
+    let expr = inner.strip_prefix("?(")?.strip_suffix(')')?;
+    let (lhs, rhs) = expr.split_once("==")?;
+    let key = lhs.trim().strip_prefix("@.")?.to_string();
+    let rhs = rhs.trim();
+    let value = unquote(rhs).unwrap_or_else(|| rhs.to_string());
+    Some(Segment::Filter { key, value })
+}
+
+fn unquote(s: &str) -> Option<String> {
+    let quoted =
+        (s.starts_with('\'') && s.ends_with('\'')) || (s.starts_with('"') && s.ends_with('"'));
+    if s.len() >= 2 && quoted {
+        Some(s[1..s.len() - 1].to_string())
+    } else {
+        None
+    }
+}
+
+fn filter_matches(element: &Value, key: &str, value: &str) -> bool {
+    match &element[key] {
+        Value::String(s) => s == value,
+        Value::Null => false,
+        other => serde_json::from_str::<Value>(value).is_ok_and(|v| v == *other),
+    }
+}
+
+fn remove_path(value: &mut Value, segments: &[Segment]) {
+    let Some((segment, rest)) = segments.split_first() else {
+        return;
+    };
+
+    if rest.is_empty() {
+        match (segment, value) {
+            (Segment::Field(key), Value::Object(obj)) => {
+                obj.remove(key);
+            }
+            (Segment::Field(key), Value::Array(arr)) => {
+                if let Ok(index) = key.parse::<usize>() {
+                    if index < arr.len() {
+                        arr.remove(index);
+                    }
+                }
+            }
+            (Segment::Index(index), Value::Array(arr)) if *index < arr.len() => {
+                arr.remove(*index);
+            }
+            (Segment::Wildcard, Value::Object(obj)) => obj.clear(),
+            (Segment::Wildcard, Value::Array(arr)) => arr.clear(),
+            (Segment::Filter { key, value }, Value::Array(arr)) => {
+                arr.retain(|element| !filter_matches(element, key, value));
+            }
+            _ => {}
+        }
+        return;
+    }
+
+    match (segment, value) {
+        (Segment::Field(key), Value::Object(obj)) => {
+            if let Some(child) = obj.get_mut(key) {
+                remove_path(child, rest);
+            }
+        }
+        (Segment::Field(key), Value::Array(arr)) => {
+            if let Some(child) = key.parse::<usize>().ok().and_then(|i| arr.get_mut(i)) {
+                remove_path(child, rest);
+            }
+        }
+        (Segment::Index(index), Value::Array(arr)) => {
+            if let Some(child) = arr.get_mut(*index) {
+                remove_path(child, rest);
+            }
+        }
+        (Segment::Wildcard, Value::Object(obj)) => {
+            for child in obj.values_mut() {
+                remove_path(child, rest);
+            }
+        }
+        (Segment::Wildcard, Value::Array(arr)) => {
+            for child in arr.iter_mut() {
+                remove_path(child, rest);
+            }
+        }
+        (Segment::Filter { key, value }, Value::Array(arr)) => {
+            for child in arr
+                .iter_mut()
+                .filter(|element| filter_matches(element, key, value))
+            {
+                remove_path(child, rest);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn field(key: &str) -> Segment {
+        Segment::Field(key.to_string())
+    }
+
+    #[test]
+    fn parses_dotted_json_path() {
+        let segments = parse_path("$.spec.template.spec").unwrap();
+        assert_eq!(
+            segments,
+            vec![field("spec"), field("template"), field("spec")]
+        );
+    }
+
+    #[test]
+    fn parses_wildcards_and_indexes() {
+        let segments = parse_path("$.spec.containers[*].ports[0]").unwrap();
+        assert_eq!(
+            segments,
+            vec![
+                field("spec"),
+                field("containers"),
+                Segment::Wildcard,
+                field("ports"),
+                Segment::Index(0),
+            ]
+        );
+        assert_eq!(parse_path("$.metadata.*").unwrap()[1], Segment::Wildcard);
+    }
+
+    #[test]
+    fn parses_quoted_keys() {
+        let segments = parse_path("$.metadata.labels['app.kubernetes.io/name']").unwrap();
+        assert_eq!(segments[2], field("app.kubernetes.io/name"));
+        let segments = parse_path(r#"$["weird]key"]"#).unwrap();
+        assert_eq!(segments, vec![field("weird]key")]);
+    }
+
+    #[test]
+    fn parses_equality_filters() {
+        let segments = parse_path("$.spec.containers[?(@.name=='app')].env").unwrap();
+        assert_eq!(
+            segments[2],
+            Segment::Filter {
+                key: "name".to_string(),
+                value: "app".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn parses_json_pointers() {
+        let segments = parse_path("/metadata/labels/app.kubernetes.io~1version").unwrap();
+        assert_eq!(
+            segments,
+            vec![
+                field("metadata"),
+                field("labels"),
+                field("app.kubernetes.io/version"),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_paths() {
+        for path in [
+            "spec.replicas",
+            "$.spec.",
+            "$..spec",
+            "$spec",
+            "$.spec[0",
+            "$.spec[-1]",
+            "$.spec[?(@.name)]",
+            "$.spec[?(name=='app')]",
+        ] {
+            assert!(parse_path(path).is_err(), "{} should be rejected", path);
+        }
+    }
+
+    #[test]
+    fn parses_bracket_contents() {
+        assert_eq!(parse_bracket("*"), Some(Segment::Wildcard));
+        assert_eq!(parse_bracket("'a.b'"), Some(field("a.b")));
+        assert_eq!(parse_bracket("2"), Some(Segment::Index(2)));
+        assert_eq!(
+            parse_bracket("?(@.port == 80)"),
+            Some(Segment::Filter {
+                key: "port".to_string(),
+                value: "80".to_string()
+            })
+        );
+        assert_eq!(parse_bracket("name"), None);
+        assert_eq!(parse_bracket("?(@.name)"), None);
+    }
+
+    #[test]
+    fn removes_paths_of_missing_fields_without_changes() {
+        let original = json!({"spec": {"ports": [{"port": 80}, {"port": "443"}]}});
+        let mut value = original.clone();
+        remove_path(&mut value, &[field("status"), field("replicas")]);
+        remove_path(&mut value, &[field("spec"), Segment::Index(5)]);
+        assert_eq!(value, original);
+
+        // Non-string filter values are compared as JSON
+        let filter = Segment::Filter {
+            key: "port".to_string(),
+            value: "80".to_string(),
+        };
+        remove_path(&mut value, &[field("spec"), field("ports"), filter]);
+        assert_eq!(value, json!({"spec": {"ports": [{"port": "443"}]}}));
+    }
+
+    #[test]
+    fn removes_selected_fields() {
+        let mut value = json!({
+            "apiVersion": "apps/v1",
+            "kind": "Deployment",
+            "metadata": {"name": "web"},
+            "spec": {
+                "replicas": 3,
+                "containers": [
+                    {"name": "app", "image": "app:1", "env": [{"name": "A"}]},
+                    {"name": "sidecar", "image": "sidecar:1", "env": [{"name": "B"}]},
+                ],
+            },
+        });
+        let rules = vec![IgnoreRule {
+            kind: Some("Deployment".to_string()),
+            paths: vec![
+                "/spec/replicas".to_string(),
+                "$.spec.containers[*].image".to_string(),
+                "$.spec.containers[?(@.name=='app')].env".to_string(),
+                "$.spec.containers[1].env[0]".to_string(),
+            ],
+            ..IgnoreRule::default()
+        }];
+
+        apply_ignore_rules(&mut value, &rules).unwrap();
+        assert_eq!(
+            value["spec"],
+            json!({"containers": [{"name": "app"}, {"name": "sidecar", "env": []}]})
+        );
+    }
+
+    #[test]
+    fn skips_rules_of_other_resources() {
+        let mut value = json!({
+            "apiVersion": "v1",
+            "kind": "Service",
+            "metadata": {"name": "web-worker"},
+            "spec": {"replicas": 1},
+        });
+        let rules = vec![IgnoreRule {
+            kind: Some("Deployment".to_string()),
+            name: Some("*-worker".to_string()),
+            paths: vec!["$.spec.replicas".to_string()],
+            ..IgnoreRule::default()
+        }];
+
+        apply_ignore_rules(&mut value, &rules).unwrap();
+        assert_eq!(value["spec"]["replicas"], 1);
+    }
+}
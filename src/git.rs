@@ -0,0 +1,107 @@
+//! Building targets at git revisions.
+//!
+//! Each revision is checked out into a temporary worktree, so the working
+//! tree of the repository is never touched.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{self, Command, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::commands::{BuildOptions, Commands};
+
+/// Distinguishes worktrees created concurrently by the same process
+static WORKTREE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Run a git command in `dir` and return its trimmed stdout
+fn git(dir: &Path, args: &[&str]) -> anyhow::Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()?;
+
+    if output.status.success() {
+        Ok(String::from_utf8(output.stdout)?.trim().to_string())
+    } else {
+        let stderr = String::from_utf8(output.stderr)?;
+        Err(anyhow::anyhow!("git {} failed: {}", args.join(" "), stderr))
+    }
+}
+
+/// A detached worktree in a temporary directory, removed on drop
+struct Worktree {
+    repo: PathBuf,
+    path: PathBuf,
+}
+
+impl Worktree {
+    fn add(repo: &Path, revision: &str) -> anyhow::Result<Self> {
+        let path = env::temp_dir().join(format!(
+            "kubediff-{}-{}",
+            process::id(),
+            WORKTREE_COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        let path_str = path.to_string_lossy().to_string();
+        git(repo, &["worktree", "add", "--detach", &path_str, revision])?;
+
+        Ok(Worktree {
+            repo: repo.to_path_buf(),
+            path,
+        })
+    }
+}
+
+impl Drop for Worktree {
+    fn drop(&mut self) {
+        let path = self.path.to_string_lossy().to_string();
+        if git(&self.repo, &["worktree", "remove", "--force", &path]).is_err() {
+            let _ = fs::remove_dir_all(&self.path);
+            let _ = git(&self.repo, &["worktree", "prune"]);
+        }
+    }
+}
+
+/// Build a target as it was at a git revision.
+///
+/// The target must exist in the working tree; it is located in the
+/// repository containing it and built from a temporary worktree of
+/// `revision` at the same relative path. Helm settings matching the target
+/// are applied to the checked out chart. A target that doesn't exist at
+/// `revision` yet builds to no documents, so all its resources are added.
+pub fn build_at_revision(
+    target: &str,
+    revision: &str,
+    options: &BuildOptions,
+) -> anyhow::Result<String> {
+    let target_path = fs::canonicalize(target)?;
+    let dir = if target_path.is_dir() {
+        target_path.as_path()
+    } else {
+        target_path
+            .parent()
+            .ok_or_else(|| anyhow::anyhow!("Invalid target: {}", target))?
+    };
+
+    let root = fs::canonicalize(git(dir, &["rev-parse", "--show-toplevel"])?)?;
+    let relative = target_path.strip_prefix(&root)?;
+
+    let worktree = Worktree::add(&root, revision)?;
+    let revision_path = worktree.path.join(relative);
+    if !revision_path.exists() {
+        return Ok(String::new());
+    }
+    let revision_target = revision_path.to_string_lossy().to_string();
+
+    let mut options = options.clone();
+    for helm in options.helm.iter_mut().filter(|h| h.matches(target)) {
+        helm.path = revision_target.clone();
+    }
+
+    Commands::get_build_with_options(&revision_target, &options)
+}
@@ -0,0 +1,103 @@
+//! Rendering of Helm chart targets.
+//!
+//! A target directory containing a `Chart.yaml` is rendered with
+//! `helm template`. Release name, namespace and values files come from the
+//! `helm` entries of the config whose path glob matches the target.
+
+use std::{
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use glob::Pattern;
+use serde::{Deserialize, Serialize};
+
+use crate::settings::expanduser;
+
+/// Release name used when none is configured and the chart path has no name
+const DEFAULT_RELEASE_NAME: &str = "kubediff";
+
+/// Rendering settings for Helm chart targets
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HelmTarget {
+    /// Glob pattern matching the target paths these settings apply to
+    pub path: String,
+    /// Release name, defaults to the chart directory name
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub release: Option<String>,
+    /// Release namespace, passed to `helm template --namespace` and used for
+    /// rendered namespaced resources without a namespace
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub namespace: Option<String>,
+    /// Values files, relative paths are resolved against the chart directory
+    #[serde(default)]
+    pub values: Vec<String>,
+}
+
+impl HelmTarget {
+    /// Whether these settings apply to the given target path
+    pub fn matches(&self, target: &str) -> bool {
+        let pattern = expanduser(&self.path);
+        let pattern = pattern.trim_end_matches('/');
+        let target = target.trim_end_matches('/');
+        pattern == target || Pattern::new(pattern).is_ok_and(|p| p.matches(target))
+    }
+}
+
+/// Whether the directory contains a Helm chart
+pub fn is_chart(target: &str) -> bool {
+    Path::new(target).join("Chart.yaml").is_file()
+}
+
+/// Release namespace configured for a chart target.
+///
+/// Rendered resources without a namespace are installed into it, so it is
+/// their default namespace instead of the one of the kubeconfig context.
+pub fn release_namespace<'a>(target: &str, helm: &'a [HelmTarget]) -> Option<&'a str> {
+    if !is_chart(target) {
+        return None;
+    }
+    helm.iter()
+        .find(|h| h.matches(target))?
+        .namespace
+        .as_deref()
+}
+
+/// Run helm template on a chart directory
+pub fn build(target: &str, settings: Option<&HelmTarget>) -> anyhow::Result<String> {
+    let release = settings
+        .and_then(|s| s.release.clone())
+        .or_else(|| {
+            Path::new(target)
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+        })
+        .unwrap_or_else(|| DEFAULT_RELEASE_NAME.to_string());
+
+    let mut command = Command::new("helm");
+    command.arg("template").arg(&release).arg(target);
+
+    if let Some(settings) = settings {
+        if let Some(namespace) = &settings.namespace {
+            command.arg("--namespace").arg(namespace);
+        }
+        for values in &settings.values {
+            let values = expanduser(values);
+            let path = Path::new(target).join(&values);
+            command.arg("--values").arg(path);
+        }
+    }
+
+    let output = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()?;
+
+    if output.status.success() {
+        Ok(String::from_utf8(output.stdout)?)
+    } else {
+        let stderr = String::from_utf8(output.stderr)?;
+        Err(anyhow::anyhow!("Helm template failed: {}", stderr))
+    }
+}
@@ -1,20 +1,98 @@
 //! Kubernetes API client wrapper for fetching live resources.
 //!
 //! This module provides a client that can fetch any Kubernetes resource
-//! using dynamic API discovery.
+//! using dynamic API discovery, cached across runs by [`crate::discovery`].
+
+use std::{future::Future, time::Duration};
 
 use anyhow::{anyhow, Result};
+use futures::future::join_all;
 use kube::{
-    api::{Api, DynamicObject, Patch, PatchParams},
-    discovery::{ApiCapabilities, ApiResource, Discovery, Scope},
-    Client,
+    api::{Api, DynamicObject, ListParams, Patch, PatchParams, TypeMeta},
+    config::{KubeConfigOptions, Kubeconfig},
+    core::Status,
+    discovery::{verbs, ApiCapabilities, ApiResource, Scope},
+    Client, Config,
 };
+use serde::Serialize;
 use serde_json::Value;
+use tokio::sync::Semaphore;
+
+use crate::{
+    discovery::{ApiIndex, DiscoveryCacheOptions},
+    error::KubediffError,
+    managed_fields::KUBEDIFF_FIELD_MANAGER,
+    settings::expanduser,
+};
+
+/// Options selecting the cluster and namespace a [`KubeClient`] talks to.
+#[derive(Debug, Clone, Default)]
+pub struct ClientOptions {
+    /// Kubeconfig context to use instead of the current context
+    pub context: Option<String>,
+    /// Kubeconfig file to read instead of `KUBECONFIG` or `~/.kube/config`
+    pub kubeconfig: Option<String>,
+    /// Namespace for resources without one, instead of the context's namespace
+    pub namespace: Option<String>,
+    /// Limits, timeouts and retries of API requests
+    pub requests: RequestPolicy,
+    /// Caching of discovered resource types across runs
+    pub discovery_cache: DiscoveryCacheOptions,
+}
+
+/// How API requests are throttled, timed out and retried.
+#[derive(Debug, Clone)]
+pub struct RequestPolicy {
+    /// Maximum number of API requests in flight at once
+    pub concurrency: usize,
+    /// Time after which a single attempt is abandoned, None to wait indefinitely
+    pub timeout: Option<Duration>,
+    /// How often a request is retried after throttling, server or network errors
+    pub retries: u32,
+    /// Delay before the first retry, doubled for every further retry
+    pub backoff: Duration,
+}
+
+impl Default for RequestPolicy {
+    fn default() -> Self {
+        RequestPolicy {
+            concurrency: 16,
+            timeout: Some(Duration::from_secs(30)),
+            retries: 3,
+            backoff: Duration::from_millis(200),
+        }
+    }
+}
+
+/// A field owned by another field manager that a server-side apply would take over
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApplyConflict {
+    /// Field manager owning the field, e.g. "kube-controller-manager"
+    pub manager: String,
+    /// Path of the conflicting field, e.g. ".spec.replicas"
+    pub field: String,
+    /// Conflict message returned by the API server
+    pub message: String,
+}
+
+/// Result of a server-side dry-run apply without force
+#[derive(Debug, Clone)]
+pub enum DryRunResult {
+    /// The normalized resource as it would exist after apply
+    Applied(Box<DynamicObject>),
+    /// The apply was rejected because other managers own some of the fields
+    Conflicts(Vec<ApplyConflict>),
+}
 
 /// A Kubernetes client wrapper with API discovery capabilities.
 pub struct KubeClient {
     client: Client,
-    discovery: Discovery,
+    discovery: ApiIndex,
+    default_namespace: String,
+    cluster_url: String,
+    requests: RequestPolicy,
+    limiter: Semaphore,
 }
 
 impl KubeClient {
@@ -25,17 +103,110 @@ impl KubeClient {
     /// - `~/.kube/config`
     /// - In-cluster service account (if running in a pod)
     pub async fn new() -> Result<Self> {
-        let client = Client::try_default().await?;
-        let discovery = Discovery::new(client.clone()).run().await?;
-        Ok(Self { client, discovery })
+        Self::with_options(&ClientOptions::default()).await
+    }
+
+    /// Create a new KubeClient for the given kubeconfig, context and namespace.
+    ///
+    /// Unset options fall back to the same sources as [`KubeClient::new`].
+    pub async fn with_options(options: &ClientOptions) -> Result<Self> {
+        let kube_config_options = KubeConfigOptions {
+            context: options.context.clone(),
+            ..Default::default()
+        };
+
+        let mut config = match (&options.kubeconfig, &options.context) {
+            (Some(path), _) => {
+                let kubeconfig = Kubeconfig::read_from(expanduser(path))?;
+                Config::from_custom_kubeconfig(kubeconfig, &kube_config_options).await?
+            }
+            (None, Some(_)) => Config::from_kubeconfig(&kube_config_options).await?,
+            (None, None) => Config::infer().await?,
+        };
+        if let Some(namespace) = &options.namespace {
+            config.default_namespace = namespace.clone();
+        }
+
+        let default_namespace = config.default_namespace.clone();
+        let cluster_url = config.cluster_url.to_string();
+        let client = Client::try_from(config)?;
+        let discovery = ApiIndex::load(&client, &cluster_url, &options.discovery_cache).await?;
+        Ok(Self {
+            client,
+            discovery,
+            default_namespace,
+            cluster_url,
+            requests: options.requests.clone(),
+            limiter: Semaphore::new(options.requests.concurrency.max(1)),
+        })
+    }
+
+    /// Namespace used for namespaced resources that don't specify one.
+    ///
+    /// This is the `--namespace` override if given, otherwise the namespace
+    /// of the kubeconfig context, falling back to "default".
+    pub fn default_namespace(&self) -> &str {
+        &self.default_namespace
+    }
+
+    /// URL of the API server this client talks to.
+    pub fn cluster_url(&self) -> &str {
+        &self.cluster_url
+    }
+
+    /// Run an API call within the request policy.
+    ///
+    /// Waits for a free slot of the concurrency limit, abandons attempts
+    /// after the timeout and retries throttled (429), server (5xx), network
+    /// and timed out attempts with exponential backoff. The outer error is a
+    /// timeout of the last attempt, the inner result that of the API call.
+    async fn request<T, F, Fut>(&self, call: F) -> Result<kube::Result<T>>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = kube::Result<T>>,
+    {
+        let mut attempt = 0;
+        loop {
+            let result = {
+                let _permit = self.limiter.acquire().await?;
+                match self.requests.timeout {
+                    Some(timeout) => tokio::time::timeout(timeout, call()).await.ok(),
+                    None => Some(call().await),
+                }
+            };
+
+            let retry = match &result {
+                Some(Ok(_)) => false,
+                Some(Err(e)) => is_transient(e),
+                None => true,
+            };
+            if !retry || attempt >= self.requests.retries {
+                return result.ok_or_else(|| {
+                    KubediffError::Timeout {
+                        message: format!(
+                            "Request timed out after {:?} ({} attempts)",
+                            self.requests.timeout.unwrap_or_default(),
+                            attempt + 1
+                        ),
+                    }
+                    .into()
+                });
+            }
+
+            tokio::time::sleep(self.requests.backoff * 2u32.saturating_pow(attempt)).await;
+            attempt += 1;
+        }
     }
 
     /// Find the API resource definition for a given apiVersion and kind.
-    fn find_api_resource(
+    ///
+    /// A miss refreshes the discovery of the group once, so types installed
+    /// since the cached discovery ran are still found.
+    async fn find_api_resource(
         &self,
         api_version: &str,
         kind: &str,
-    ) -> Option<(ApiResource, ApiCapabilities)> {
+    ) -> Result<Option<(ApiResource, ApiCapabilities)>> {
         // Parse apiVersion into group and version
         // e.g., "apps/v1" -> ("apps", "v1"), "v1" -> ("", "v1")
         let (group, version) = if let Some(pos) = api_version.find('/') {
@@ -44,18 +215,19 @@ impl KubeClient {
             ("", api_version)
         };
 
-        // Search through discovered API groups
-        for group_info in self.discovery.groups() {
-            if group_info.name() == group {
-                for (ar, caps) in group_info.recommended_resources() {
-                    if ar.kind == kind && ar.version == version {
-                        return Some((ar, caps));
-                    }
-                }
-            }
+        if let Some(found) = self.discovery.find(group, version, kind) {
+            return Ok(Some(found));
         }
+        if self.discovery.refresh_group(&self.client, group).await? {
+            return Ok(self.discovery.find(group, version, kind));
+        }
+        Ok(None)
+    }
 
-        None
+    /// Whether a resource type is namespaced, None if the API server doesn't serve it
+    pub async fn is_namespaced(&self, api_version: &str, kind: &str) -> Result<Option<bool>> {
+        let found = self.find_api_resource(api_version, kind).await?;
+        Ok(found.map(|(_, capabilities)| matches!(capabilities.scope, Scope::Namespaced)))
     }
 
     /// Fetch a live resource from the Kubernetes cluster.
@@ -79,17 +251,21 @@ impl KubeClient {
     ) -> Result<Option<DynamicObject>> {
         let (ar, caps) = self
             .find_api_resource(api_version, kind)
-            .ok_or_else(|| anyhow!("Unknown resource type: {}/{}", api_version, kind))?;
+            .await?
+            .ok_or_else(|| KubediffError::UnknownResourceType {
+                api_version: api_version.to_string(),
+                kind: kind.to_string(),
+            })?;
 
         let api: Api<DynamicObject> = match caps.scope {
             Scope::Namespaced => {
-                let ns = namespace.unwrap_or("default");
+                let ns = namespace.unwrap_or(self.default_namespace());
                 Api::namespaced_with(self.client.clone(), ns, &ar)
             }
             Scope::Cluster => Api::all_with(self.client.clone(), &ar),
         };
 
-        match api.get_opt(name).await {
+        match self.request(|| api.get_opt(name)).await? {
             Ok(obj) => Ok(obj),
             Err(kube::Error::Api(err)) if err.code == 404 => Ok(None),
             Err(e) => Err(e.into()),
@@ -108,6 +284,62 @@ impl KubeClient {
     /// * `Ok(object)` - The normalized resource as it would exist after apply
     /// * `Err(_)` - API error or validation failure
     pub async fn apply_dry_run(&self, manifest: &Value) -> Result<DynamicObject> {
+        self.apply_dry_run_as(manifest, KUBEDIFF_FIELD_MANAGER)
+            .await
+    }
+
+    /// Apply a resource with forced server-side dry-run as `field_manager`.
+    ///
+    /// Like `kubectl apply --server-side --force-conflicts`, the fields
+    /// owned by other managers are taken over by `field_manager`.
+    pub async fn apply_dry_run_as(
+        &self,
+        manifest: &Value,
+        field_manager: &str,
+    ) -> Result<DynamicObject> {
+        self.dry_run_patch(manifest, field_manager, true).await
+    }
+
+    /// Apply a resource with server-side dry-run without forcing field ownership.
+    ///
+    /// Like `kubectl apply --server-side` without `--force-conflicts`, the API
+    /// server rejects the apply if it would take over fields owned by another
+    /// field manager.
+    ///
+    /// # Arguments
+    /// * `manifest` - The resource manifest as a JSON Value
+    /// * `field_manager` - The field manager the manifests are deployed with
+    ///
+    /// # Returns
+    /// * `Ok(DryRunResult::Applied(object))` - The normalized resource
+    /// * `Ok(DryRunResult::Conflicts(conflicts))` - Fields owned by other managers
+    /// * `Err(_)` - Any other API error or validation failure
+    pub async fn apply_dry_run_without_force(
+        &self,
+        manifest: &Value,
+        field_manager: &str,
+    ) -> Result<DryRunResult> {
+        match self.dry_run_patch(manifest, field_manager, false).await {
+            Ok(object) => Ok(DryRunResult::Applied(Box::new(object))),
+            Err(e) => match e.downcast_ref::<kube::Error>() {
+                Some(kube::Error::Api(status)) if status.code == 409 => {
+                    let conflicts = parse_conflicts(status)
+                        .into_iter()
+                        .filter(|conflict| conflict.manager != field_manager)
+                        .collect();
+                    Ok(DryRunResult::Conflicts(conflicts))
+                }
+                _ => Err(e),
+            },
+        }
+    }
+
+    async fn dry_run_patch(
+        &self,
+        manifest: &Value,
+        field_manager: &str,
+        force: bool,
+    ) -> Result<DynamicObject> {
         let api_version = manifest["apiVersion"]
             .as_str()
             .ok_or_else(|| anyhow!("Missing apiVersion"))?;
@@ -121,23 +353,163 @@ impl KubeClient {
 
         let (ar, caps) = self
             .find_api_resource(api_version, kind)
-            .ok_or_else(|| anyhow!("Unknown resource type: {}/{}", api_version, kind))?;
+            .await?
+            .ok_or_else(|| KubediffError::UnknownResourceType {
+                api_version: api_version.to_string(),
+                kind: kind.to_string(),
+            })?;
 
         let api: Api<DynamicObject> = match caps.scope {
             Scope::Namespaced => {
-                let ns = namespace.unwrap_or("default");
+                let ns = namespace.unwrap_or(self.default_namespace());
                 Api::namespaced_with(self.client.clone(), ns, &ar)
             }
             Scope::Cluster => Api::all_with(self.client.clone(), &ar),
         };
 
         // Use server-side apply with dry-run - this applies all server defaults
-        // Force is needed to bypass field ownership conflicts (safe since it's dry-run only)
-        let patch_params = PatchParams::apply("kubediff").dry_run().force();
-        let result = api
-            .patch(name, &patch_params, &Patch::Apply(manifest))
-            .await?;
+        // Force bypasses field ownership conflicts (safe since it's dry-run only)
+        let mut patch_params = PatchParams::apply(field_manager).dry_run();
+        if force {
+            patch_params = patch_params.force();
+        }
+        let patch = Patch::Apply(manifest);
+        let result = self
+            .request(|| api.patch(name, &patch_params, &patch))
+            .await??;
 
         Ok(result)
     }
+
+    /// List live objects of every listable resource type matching a label selector.
+    ///
+    /// Namespaced resources are listed across all namespaces. Resource types
+    /// the caller is not allowed to list are skipped.
+    ///
+    /// # Arguments
+    /// * `label_selector` - A label selector (e.g., "app=my-app,tier!=cache")
+    ///
+    /// # Returns
+    /// * `Ok(objects)` - Matching objects with apiVersion and kind set
+    /// * `Err(_)` - API error other than a permission or not-found failure
+    pub async fn list_resources(&self, label_selector: &str) -> Result<Vec<DynamicObject>> {
+        self.list_resources_of(label_selector, |_, _| true).await
+    }
+
+    /// [`KubeClient::list_resources`] restricted to the resource types accepted
+    /// by `include`, which is called with the API group and kind of each type.
+    pub async fn list_resources_of(
+        &self,
+        label_selector: &str,
+        include: impl Fn(&str, &str) -> bool,
+    ) -> Result<Vec<DynamicObject>> {
+        let list_params = ListParams::default().labels(label_selector);
+
+        let futures: Vec<_> = self
+            .discovery
+            .recommended_resources()
+            .into_iter()
+            .filter(|(ar, caps)| {
+                caps.supports_operation(verbs::LIST) && include(&ar.group, &ar.kind)
+            })
+            .map(|(ar, _)| {
+                let list_params = list_params.clone();
+                async move {
+                    let api: Api<DynamicObject> = Api::all_with(self.client.clone(), &ar);
+                    match self.request(|| api.list(&list_params)).await? {
+                        Ok(list) => Ok(list
+                            .items
+                            .into_iter()
+                            .map(|mut obj| {
+                                // List items don't carry apiVersion and kind
+                                obj.types = Some(TypeMeta {
+                                    api_version: ar.api_version.clone(),
+                                    kind: ar.kind.clone(),
+                                });
+                                obj
+                            })
+                            .collect()),
+                        Err(kube::Error::Api(err)) if [403, 404, 405].contains(&err.code) => {
+                            Ok(vec![])
+                        }
+                        Err(e) => Err(anyhow::Error::from(e)),
+                    }
+                }
+            })
+            .collect();
+
+        let mut objects = vec![];
+        for result in join_all(futures).await {
+            objects.extend(result?);
+        }
+        Ok(objects)
+    }
+}
+
+/// Name of the namespace a request failed on because it doesn't exist
+pub fn missing_namespace(error: &anyhow::Error) -> Option<&str> {
+    match error.downcast_ref::<kube::Error>()? {
+        kube::Error::Api(status) if status.code == 404 => {
+            let details = status.details.as_ref()?;
+            (details.kind == "namespaces").then_some(details.name.as_str())
+        }
+        _ => None,
+    }
+}
+
+/// Whether the API server denied a request for lack of permissions (403)
+pub fn is_forbidden(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<kube::Error>(),
+        Some(kube::Error::Api(status)) if status.code == 403
+    )
+}
+
+/// Read the conflicting fields of a 409 apply response.
+///
+/// Causes look like `{"reason": "FieldManagerConflict", "field": ".spec.replicas",
+/// "message": "conflict with \"hpa\" using apps/v1"}`.
+pub(crate) fn parse_conflicts(status: &Status) -> Vec<ApplyConflict> {
+    let causes = status
+        .details
+        .as_ref()
+        .map(|details| details.causes.as_slice())
+        .unwrap_or_default();
+
+    let conflicts: Vec<ApplyConflict> = causes
+        .iter()
+        .filter(|cause| cause.reason == "FieldManagerConflict")
+        .map(|cause| ApplyConflict {
+            manager: conflicting_manager(&cause.message).unwrap_or_default(),
+            field: cause.field.clone(),
+            message: cause.message.clone(),
+        })
+        .collect();
+
+    if conflicts.is_empty() {
+        // Conflict without details, e.g. from an older API server
+        vec![ApplyConflict {
+            manager: conflicting_manager(&status.message).unwrap_or_default(),
+            field: String::new(),
+            message: status.message.clone(),
+        }]
+    } else {
+        conflicts
+    }
+}
+
+/// Manager name from a message like `conflict with "hpa" using apps/v1`
+fn conflicting_manager(message: &str) -> Option<String> {
+    let (_, rest) = message.split_once("conflict with \"")?;
+    let (manager, _) = rest.split_once('"')?;
+    Some(manager.to_string())
+}
+
+/// Whether a failed request may succeed when retried
+fn is_transient(error: &kube::Error) -> bool {
+    match error {
+        kube::Error::Api(status) => status.code == 429 || (500..=599).contains(&status.code),
+        kube::Error::HyperError(_) | kube::Error::Service(_) => true,
+        _ => false,
+    }
 }
@@ -52,16 +52,40 @@
 //! ```
 
 pub mod commands;
+pub mod compare;
 pub mod diff;
+pub mod discovery;
 pub mod enums;
+pub mod error;
+pub mod field_diff;
 pub mod filter;
+pub mod git;
+pub mod helm;
 pub mod kube_client;
 pub mod kustomize;
+pub mod managed_fields;
 pub mod processor;
+pub mod report;
+pub mod resource;
+pub mod secrets;
+pub mod selector;
 pub mod settings;
+pub mod snapshot;
+pub mod three_way;
 
 // Re-export main types for convenience
-pub use enums::LogLevel;
-pub use kube_client::KubeClient;
-pub use processor::{DiffResult, Process, TargetResult};
+pub use commands::BuildOptions;
+pub use compare::Compare;
+pub use discovery::DiscoveryCacheOptions;
+pub use enums::{ChangeStatus, LogLevel, Normalization};
+pub use error::{KubediffError, ValidationCause};
+pub use field_diff::{ChangeOrigin, FieldChange, FieldChangeKind};
+pub use filter::IgnoreRule;
+pub use helm::HelmTarget;
+pub use kube_client::{ApplyConflict, ClientOptions, KubeClient, RequestPolicy};
+pub use processor::{DiffOptions, DiffResult, Process, PruneOptions, TargetResult};
+pub use report::{Report, Summary, TargetSummary};
+pub use resource::ResourceId;
+pub use selector::{LabelSelector, ResourceSelector};
 pub use settings::Settings;
+pub use snapshot::Snapshot;
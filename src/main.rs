@@ -3,15 +3,23 @@ mod logger;
 mod print;
 
 use std::{
+    collections::HashSet,
+    io,
     path::Path,
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
 // Import from the library crate
-use kubediff::{KubeClient, LogLevel, Process, Settings};
+use kubediff::{
+    commands::STDIN_TARGET, BuildOptions, ChangeOrigin, ChangeStatus, ClientOptions, Compare,
+    DiffOptions, DiffResult, DiscoveryCacheOptions, FieldChangeKind, KubeClient, LabelSelector,
+    LogLevel, Normalization, Process, PruneOptions, Report, RequestPolicy, ResourceSelector,
+    Settings, Snapshot, Summary, TargetResult,
+};
 
 use crate::{logger::Logger, print::Pretty};
-use clap::{Parser, ValueEnum};
+use clap::{Parser, Subcommand, ValueEnum};
 use colored::Colorize;
 
 /// CLI-specific LogLevel that implements clap's ValueEnum
@@ -33,85 +41,469 @@ impl From<CliLogLevel> for LogLevel {
     }
 }
 
+/// Format used to print the results
+#[derive(Default, Debug, Copy, Clone, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+    Yaml,
+}
+
+#[derive(Debug, Subcommand, Clone)]
+pub enum Command {
+    /// Compare two manifest sources offline, without cluster access
+    Compare {
+        /// Original manifests: file, directory, kustomization, Helm chart or `-` for stdin
+        left: String,
+        /// Modified manifests: file, directory, kustomization, Helm chart or `-` for stdin
+        right: String,
+    },
+    /// Compare the live objects of two clusters
+    ///
+    /// Resources are taken from the targets selected by --env, --path or
+    /// --inplace, or listed by --list-selector in both clusters.
+    Clusters {
+        /// Kubeconfig context of the original cluster
+        #[clap(long, value_parser)]
+        left_context: String,
+        /// Kubeconfig context of the modified cluster
+        #[clap(long, value_parser)]
+        right_context: String,
+        /// Compare all objects matching this label selector instead of the targets' resources
+        #[clap(long, value_parser)]
+        list_selector: Option<String>,
+    },
+    /// Write the live objects of the targets' resources to a snapshot directory
+    Snapshot {
+        /// Directory to write the snapshot to
+        dir: String,
+        /// Compare the live objects against an existing snapshot instead of writing one
+        #[clap(long, value_parser)]
+        diff: bool,
+    },
+}
+
 #[derive(Debug, Parser, Clone)]
 #[clap(author, version, about, long_about = None)]
 pub struct Cli {
+    #[clap(subcommand)]
+    command: Option<Command>,
     #[clap(short, long, value_parser)]
     env: Option<String>,
     #[clap(short, long, value_parser)]
     inplace: bool,
     #[clap(short, long, value_parser)]
     path: Option<String>,
-    #[clap(short, long, value_enum)]
-    log: Option<CliLogLevel>,
+    /// Manifest file or directory to diff, `-` reads manifests from stdin
     #[clap(short, long, value_parser)]
+    filename: Option<String>,
+    /// Read manifests from stdin, same as `-f -`
+    #[clap(long, value_parser)]
+    stdin: bool,
+    #[clap(short, long, value_enum, global = true)]
+    log: Option<CliLogLevel>,
+    #[clap(short, long, value_parser, global = true)]
     term_width: Option<usize>,
+    #[clap(short, long, value_enum, default_value_t, global = true)]
+    output: OutputFormat,
+    /// Exit with 1 when changes are found and 2 or higher on errors, like `kubectl diff`
+    #[clap(long, value_parser, global = true)]
+    exit_code: bool,
+    /// Report live objects of the rendered kinds matching this label selector that are missing from the manifests
+    #[clap(long, value_parser)]
+    prune_selector: Option<String>,
+    /// Report live objects of this ApplySet that are missing from the manifests
+    #[clap(long, value_parser)]
+    prune_applyset: Option<String>,
+    /// Kubeconfig context to use, overrides the context mapped to the env in the config
+    #[clap(long, value_parser)]
+    context: Option<String>,
+    /// Path to the kubeconfig file to use
+    #[clap(long, value_parser)]
+    kubeconfig: Option<String>,
+    /// Namespace for resources that don't specify one, defaults to the context's namespace
+    #[clap(short, long, value_parser)]
+    namespace: Option<String>,
+    /// Print Secret data in diffs instead of masking it
+    #[clap(long, value_parser, global = true)]
+    show_secrets: bool,
+    /// Mask Secret data with SHA-256 fingerprints so changed values can be told apart
+    ///
+    /// Fingerprints are unsalted so they stay comparable across runs. Weak values such as
+    /// short passwords can be recovered from them by trying candidates, don't use this
+    /// where the output is public, e.g. in CI logs of public repositories.
+    #[clap(long, value_parser, global = true)]
+    secret_fingerprints: bool,
+    /// Read manifests from subdirectories of plain directories too
+    #[clap(short = 'R', long, value_parser, global = true)]
+    recursive: bool,
+    /// Only read manifest files matching this glob, relative to the target
+    #[clap(long = "include-files", value_parser, global = true)]
+    include_files: Vec<String>,
+    /// Skip manifest files matching this glob, relative to the target
+    #[clap(long = "exclude-files", value_parser, global = true)]
+    exclude_files: Vec<String>,
+    /// Diff the rendered manifests against this git revision instead of the cluster
+    #[clap(long, value_parser)]
+    git_base: Option<String>,
+    /// Git revision to compare with --git-base, defaults to the working tree
+    #[clap(long, value_parser, requires = "git_base")]
+    git_head: Option<String>,
+    /// Classify changed fields as local change, live drift or conflict using what was last applied
+    #[clap(long, value_parser, global = true)]
+    three_way: bool,
+    /// Field manager treated as ours in three-way and owned-only diffs, overrides the config
+    #[clap(long = "field-manager", value_parser, global = true)]
+    field_managers: Vec<String>,
+    /// Only compare fields owned by our field managers, hiding fields set by controllers
+    #[clap(long, value_parser, global = true)]
+    owned_only: bool,
+    /// Dry-run as the first --field-manager (default kubectl) without --force-conflicts and report fields owned by other field managers
+    #[clap(long, value_parser, global = true)]
+    report_conflicts: bool,
+    /// Maximum number of API requests in flight at once
+    #[clap(long, value_parser, global = true)]
+    concurrency: Option<usize>,
+    /// Timeout of a single API request in seconds, 0 disables it
+    #[clap(long, value_parser, global = true)]
+    request_timeout: Option<u64>,
+    /// How often throttled, failed or timed out API requests are retried
+    #[clap(long, value_parser, global = true)]
+    retries: Option<u32>,
+    /// Compare against the live objects without a server-side dry-run, defaults are not applied
+    #[clap(long, value_parser, global = true)]
+    client_side: bool,
+    /// Seconds discovered resource types are cached across runs, 0 disables the cache
+    #[clap(long, value_parser, global = true)]
+    discovery_ttl: Option<u64>,
+    /// Only diff resources of this kind, can be repeated
+    #[clap(long = "kind", value_parser, global = true)]
+    kinds: Vec<String>,
+    /// Only diff resources whose name matches this glob, can be repeated
+    #[clap(long = "name", value_parser, global = true)]
+    names: Vec<String>,
+    /// Only diff resources in a namespace matching this glob, can be repeated
+    ///
+    /// Unlike kubectl's -n, --namespace sets the namespace of resources without one instead.
+    #[clap(long = "resource-namespace", value_parser, global = true)]
+    resource_namespaces: Vec<String>,
+    /// Only diff resources whose labels match this selector, e.g. `app=foo,tier!=db`
+    ///
+    /// There is no -l short flag like in kubectl, -l is --log.
+    #[clap(long = "selector", value_parser, global = true)]
+    label_selector: Option<LabelSelector>,
+    /// Skip resources of this kind, can be repeated
+    #[clap(long = "exclude-kind", value_parser, global = true)]
+    exclude_kinds: Vec<String>,
+    /// Skip resources whose name matches this glob, can be repeated
+    #[clap(long = "exclude-name", value_parser, global = true)]
+    exclude_names: Vec<String>,
+    /// Skip resources in a namespace matching this glob, can be repeated
+    #[clap(long = "exclude-resource-namespace", value_parser, global = true)]
+    exclude_resource_namespaces: Vec<String>,
+    /// Only print the summary table and the resources that differ, without the diffs
+    #[clap(long, value_parser, global = true)]
+    summary_only: bool,
+    /// Diff the rendered manifests against a snapshot directory instead of the cluster
+    #[clap(long, value_parser, conflicts_with = "git_base")]
+    snapshot: Option<String>,
+}
+
+impl Cli {
+    /// Whether the diffs of each target are printed as they are processed
+    fn print_diffs(&self) -> bool {
+        self.output == OutputFormat::Text && !self.summary_only
+    }
+
+    fn client_options(&self, settings: &Settings) -> ClientOptions {
+        let context = self.context.clone().or_else(|| {
+            self.env
+                .as_ref()
+                .and_then(|env| settings.configs.contexts.get(env))
+                .cloned()
+        });
+
+        ClientOptions {
+            context,
+            kubeconfig: self.kubeconfig.clone(),
+            namespace: self.namespace.clone(),
+            requests: self.request_policy(settings),
+            discovery_cache: DiscoveryCacheOptions {
+                ttl: match self.discovery_ttl.or(settings.configs.discovery_ttl) {
+                    Some(seconds) => Duration::from_secs(seconds),
+                    None => DiscoveryCacheOptions::default().ttl,
+                },
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Request limits from the flags, falling back to the config and the client defaults
+    fn request_policy(&self, settings: &Settings) -> RequestPolicy {
+        let config = &settings.configs.requests;
+        let defaults = RequestPolicy::default();
+
+        RequestPolicy {
+            concurrency: self
+                .concurrency
+                .or(config.concurrency)
+                .unwrap_or(defaults.concurrency),
+            timeout: match self.request_timeout.or(config.timeout) {
+                Some(0) => None,
+                Some(seconds) => Some(Duration::from_secs(seconds)),
+                None => defaults.timeout,
+            },
+            retries: self.retries.or(config.retries).unwrap_or(defaults.retries),
+            ..defaults
+        }
+    }
+
+    fn diff_options(&self, settings: &Settings) -> DiffOptions {
+        let prune = if self.prune_selector.is_some() || self.prune_applyset.is_some() {
+            Some(PruneOptions {
+                selector: self.prune_selector.clone(),
+                applyset: self.prune_applyset.clone(),
+            })
+        } else {
+            None
+        };
+
+        DiffOptions {
+            prune,
+            ignore_rules: settings.configs.ignore.clone(),
+            show_secrets: self.show_secrets,
+            secret_fingerprints: self.secret_fingerprints,
+            build: BuildOptions {
+                helm: settings.configs.helm.clone(),
+                recursive: self.recursive,
+                include: self.include_files.clone(),
+                exclude: self.exclude_files.clone(),
+            },
+            three_way: self.three_way,
+            field_managers: if self.field_managers.is_empty() {
+                settings.configs.field_managers.clone()
+            } else {
+                self.field_managers.clone()
+            },
+            owned_only: self.owned_only,
+            report_conflicts: self.report_conflicts,
+            client_side: self.client_side,
+            selector: ResourceSelector {
+                kinds: self.kinds.clone(),
+                names: self.names.clone(),
+                namespaces: self.resource_namespaces.clone(),
+                labels: self.label_selector.clone(),
+                exclude_kinds: self.exclude_kinds.clone(),
+                exclude_names: self.exclude_names.clone(),
+                exclude_namespaces: self.exclude_resource_namespaces.clone(),
+            },
+            default_namespace: self.namespace.clone(),
+        }
+    }
+}
+
+/// What the rendered manifests are compared against
+enum Baseline {
+    /// Live objects in the cluster
+    Cluster(Box<KubeClient>),
+    /// The manifests rendered at a git revision
+    GitRevision { base: String, head: Option<String> },
+    /// Live objects stored in a snapshot directory
+    Snapshot(Snapshot),
+}
+
+impl Baseline {
+    async fn diff_target(&self, target: &str, options: &DiffOptions) -> TargetResult {
+        match self {
+            Baseline::Cluster(client) => {
+                Process::process_target_with_options(client, target, options).await
+            }
+            Baseline::GitRevision { base, head } => {
+                Compare::revisions(target, base, head.as_deref(), options)
+            }
+            Baseline::Snapshot(snapshot) => snapshot.diff_manifests(target, options),
+        }
+    }
 }
 
+/// Exit code used when no differences were found
+const EXIT_NO_CHANGES: i32 = 0;
+/// Exit code used when at least one resource differs from the cluster
+const EXIT_CHANGES: i32 = 1;
+/// Exit code used when building, parsing or talking to the cluster failed,
+/// or when applying would conflict with another field manager
+const EXIT_ERROR: i32 = 2;
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let args = Cli::parse();
-    let mut settings = Settings::load().expect("Failed to load config file!");
+    let exit_code = args.exit_code;
+    match run(args).await {
+        // Exit code 1 means changes were found, failures must not look like drift
+        Err(e) if exit_code => {
+            eprintln!("Error: {:?}", e);
+            std::process::exit(EXIT_ERROR);
+        }
+        result => result,
+    }
+}
+
+async fn run(args: Cli) -> anyhow::Result<()> {
+    let mut settings = match Settings::load() {
+        Ok(settings) => settings,
+        // The config file is optional, e.g. for offline compares
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Settings::default(),
+        Err(e) => anyhow::bail!("Failed to load config file {}: {}", Settings::path(), e),
+    };
 
     // Determine the effective log level
-    let log_level = args
-        .log
-        .map(LogLevel::from)
-        .unwrap_or(settings.configs.log);
+    let log_level = args.log.map(LogLevel::from).unwrap_or(settings.configs.log);
 
     // Create logger with resolved log level
     let logger = Arc::new(Mutex::new(Logger::new(log_level, args.term_width)));
 
-    // Initialize Kubernetes client
-    let client = match KubeClient::new().await {
-        Ok(c) => c,
-        Err(e) => {
-            logger
-                .lock()
-                .unwrap()
-                .log_error(format!("Failed to connect to Kubernetes cluster: {}", e));
-            return Err(e);
+    let options = args.diff_options(&settings);
+
+    // Offline comparison of two sources, no targets or cluster involved
+    if let Some(Command::Compare { left, right }) = &args.command {
+        let result = Compare::sources(left, right, &options);
+        if args.print_diffs() {
+            Pretty::print_path(format!("Compare: {}", result.target), args.term_width);
+            print_target_result(&result, &logger, args.term_width);
         }
+        return finish(&args, vec![result], false);
+    }
+
+    // Live objects of two clusters compared against each other
+    if let Some(Command::Clusters {
+        left_context,
+        right_context,
+        list_selector,
+    }) = &args.command
+    {
+        let client_options = |context: &String| ClientOptions {
+            context: Some(context.clone()),
+            ..args.client_options(&settings)
+        };
+        let (left_options, right_options) =
+            (client_options(left_context), client_options(right_context));
+        let (left, right) = match futures::try_join!(
+            KubeClient::with_options(&left_options),
+            KubeClient::with_options(&right_options)
+        ) {
+            Ok(clients) => clients,
+            Err(e) => {
+                logger
+                    .lock()
+                    .unwrap()
+                    .log_error(format!("Failed to connect to Kubernetes cluster: {}", e));
+                if args.exit_code {
+                    std::process::exit(EXIT_ERROR);
+                }
+                return Err(e);
+            }
+        };
+
+        let mut target_results: Vec<TargetResult> = vec![];
+        if let Some(selector) = list_selector {
+            target_results
+                .push(Compare::clusters_by_selector(&left, &right, selector, &options).await);
+        } else {
+            let targets = Process::get_entries(
+                args.env.clone(),
+                args.inplace,
+                args.path.clone(),
+                &mut settings,
+            );
+            for target in targets {
+                target_results.push(Compare::clusters(&left, &right, &target, &options).await);
+            }
+        }
+
+        if args.print_diffs() {
+            for result in &target_results {
+                Pretty::print_path(
+                    format!(
+                        "Clusters {} -> {}: {}",
+                        left_context, right_context, result.target
+                    ),
+                    args.term_width,
+                );
+                print_target_result(result, &logger, args.term_width);
+            }
+        }
+        return finish(&args, target_results, false);
+    }
+
+    if matches!(args.command, Some(Command::Snapshot { .. }))
+        && (args.git_base.is_some() || args.snapshot.is_some())
+    {
+        anyhow::bail!("The snapshot command reads the cluster, it can't be used with --git-base or --snapshot");
+    }
+
+    // Initialize Kubernetes client, unless diffing offline
+    let baseline = match (&args.git_base, &args.snapshot) {
+        (Some(base), _) => Baseline::GitRevision {
+            base: base.clone(),
+            head: args.git_head.clone(),
+        },
+        (None, Some(dir)) => Baseline::Snapshot(Snapshot::open(Path::new(dir))?),
+        (None, None) => match KubeClient::with_options(&args.client_options(&settings)).await {
+            Ok(c) => Baseline::Cluster(Box::new(c)),
+            Err(e) => {
+                logger
+                    .lock()
+                    .unwrap()
+                    .log_error(format!("Failed to connect to Kubernetes cluster: {}", e));
+                if args.exit_code {
+                    std::process::exit(EXIT_ERROR);
+                }
+                return Err(e);
+            }
+        },
     };
 
     // Get target paths using library function
-    let targets = Process::get_entries(
-        args.env.clone(),
-        args.inplace,
-        args.path.clone(),
-        &mut settings,
-    );
+    let path = if args.stdin {
+        Some(STDIN_TARGET.to_string())
+    } else {
+        args.filename.clone().or(args.path.clone())
+    };
+    let targets = Process::get_entries(args.env.clone(), args.inplace, path, &mut settings);
+
+    if let (Some(Command::Snapshot { dir, diff }), Baseline::Cluster(client)) =
+        (&args.command, &baseline)
+    {
+        return snapshot(
+            &args,
+            client,
+            targets,
+            Path::new(dir),
+            *diff,
+            &options,
+            &logger,
+        )
+        .await;
+    }
+
+    let mut target_results: Vec<TargetResult> = vec![];
+    let mut missing_targets = false;
 
     for target in targets {
-        if Path::new(&target).exists() {
+        if !args.print_diffs() {
+            target_results.push(baseline.diff_target(&target, &options).await);
+            continue;
+        }
+
+        if target == STDIN_TARGET || Path::new(&target).exists() {
             // Print the path header (CLI-only display)
             Pretty::print_path(format!("Path: {}", target), args.term_width);
 
             // Use library to get structured results
-            let result = Process::process_target(&client, &target).await;
-
-            // Handle build errors
-            if let Some(error) = result.build_error {
-                logger.lock().unwrap().log_error(error);
-                continue;
-            }
-
-            // Process and display each diff result
-            for diff_result in result.results {
-                if let Some(ref diff) = diff_result.diff {
-                    // Has changes - print the diff
-                    Pretty::print(diff.clone(), Some(&diff_result.resource_name), args.term_width);
-                } else if let Some(ref error) = diff_result.error {
-                    // Error occurred
-                    logger.lock().unwrap().log_error(error.clone());
-                } else {
-                    // No changes
-                    logger.lock().unwrap().log_info(format!(
-                        "No changes in: {:?} {:?} {:?}\n",
-                        diff_result.api_version, diff_result.kind, diff_result.resource_name
-                    ));
-                }
-            }
+            let result = baseline.diff_target(&target, &options).await;
+            print_target_result(&result, &logger, args.term_width);
+            target_results.push(result);
         } else {
             let message = "Must build at directory: not a valid directory"
                 .yellow()
@@ -119,9 +511,271 @@ async fn main() -> anyhow::Result<()> {
             logger
                 .lock()
                 .unwrap()
-                .log_warning(format!("\n{}:{}\n", message, &target))
+                .log_warning(format!("\n{}:{}\n", message, &target));
+            missing_targets = true;
         }
     }
 
+    finish(&args, target_results, missing_targets)
+}
+
+/// Write a snapshot of the targets' live objects, or diff them against one
+async fn snapshot(
+    args: &Cli,
+    client: &KubeClient,
+    targets: HashSet<String>,
+    dir: &Path,
+    diff: bool,
+    options: &DiffOptions,
+    logger: &Arc<Mutex<Logger>>,
+) -> anyhow::Result<()> {
+    if diff {
+        let snapshot = Snapshot::open(dir)?;
+        let mut target_results = vec![];
+        for target in targets {
+            let result = snapshot.diff_live(client, &target, options).await;
+            if args.print_diffs() {
+                Pretty::print_path(
+                    format!("Snapshot {}: {}", dir.display(), target),
+                    args.term_width,
+                );
+                print_target_result(&result, logger, args.term_width);
+            }
+            target_results.push(result);
+        }
+        return finish(args, target_results, false);
+    }
+
+    let snapshot = Snapshot::create(client, dir)?;
+    let mut failed = false;
+    for target in targets {
+        match snapshot.capture(client, &target, options).await {
+            Ok(capture) => {
+                let logger = logger.lock().unwrap();
+                for (id, error) in &capture.failed {
+                    logger.log_error(format!("Failed to snapshot {}: {}", id, error));
+                }
+                failed |= !capture.failed.is_empty();
+                logger.log_info(format!(
+                    "Wrote {} objects of {} to {}\n",
+                    capture.written.len(),
+                    target,
+                    dir.display()
+                ));
+            }
+            Err(e) => {
+                logger
+                    .lock()
+                    .unwrap()
+                    .log_error(format!("Failed to snapshot {}: {}", target, e));
+                failed = true;
+            }
+        }
+    }
+
+    if failed && args.exit_code {
+        std::process::exit(EXIT_ERROR);
+    }
+    Ok(())
+}
+
+/// Print structured output and exit with the requested exit code
+fn finish(
+    args: &Cli,
+    target_results: Vec<TargetResult>,
+    missing_targets: bool,
+) -> anyhow::Result<()> {
+    let code = if missing_targets {
+        EXIT_ERROR
+    } else {
+        exit_code(&target_results)
+    };
+
+    match args.output {
+        OutputFormat::Text => print_summary(&target_results, args.summary_only, args.term_width),
+        OutputFormat::Json => println!("{}", Report::new(target_results).to_json()?),
+        OutputFormat::Yaml => print!("{}", Report::new(target_results).to_yaml()?),
+    }
+
+    if args.exit_code {
+        std::process::exit(code);
+    }
+
     Ok(())
 }
+
+/// Compute the process exit code from the collected results
+fn exit_code(results: &[TargetResult]) -> i32 {
+    if results.iter().any(TargetResult::has_errors)
+        || results.iter().any(TargetResult::has_conflicts)
+    {
+        EXIT_ERROR
+    } else if results.iter().any(TargetResult::has_changes) {
+        EXIT_CHANGES
+    } else {
+        EXIT_NO_CHANGES
+    }
+}
+
+fn print_target_result(
+    result: &TargetResult,
+    logger: &Arc<Mutex<Logger>>,
+    term_width: Option<usize>,
+) {
+    // Handle build errors
+    if let Some(ref error) = result.build_error {
+        logger.lock().unwrap().log_error(error.to_string());
+        return;
+    }
+
+    // Process and display each diff result
+    for diff_result in &result.results {
+        for conflict in &diff_result.conflicts {
+            logger.lock().unwrap().log_error(format!(
+                "Conflict in {} {}: {} is owned by {:?}\n",
+                diff_result.kind, diff_result.resource_name, conflict.field, conflict.manager
+            ));
+        }
+        if let Some(ref diff) = diff_result.diff {
+            // Has changes - print the diff
+            let name = match diff_result.normalization {
+                Normalization::ClientSide => format!(
+                    "{} (client-side, defaults not applied)",
+                    diff_result.resource_name
+                ),
+                Normalization::Unvalidated => format!(
+                    "{} (new, cannot be validated until CRD/namespace exists)",
+                    diff_result.resource_name
+                ),
+                _ => diff_result.resource_name.clone(),
+            };
+            Pretty::print(diff.clone(), Some(&name), term_width);
+            print_change_origins(diff_result, logger);
+        } else if let Some(ref error) = diff_result.error {
+            // Error occurred
+            logger.lock().unwrap().log_error(error.to_string());
+        } else if diff_result.status == ChangeStatus::Skipped {
+            logger.lock().unwrap().log_warning(format!(
+                "Skipped: {:?} {:?} {:?} (the manifests failed to parse)\n",
+                diff_result.api_version, diff_result.kind, diff_result.resource_name
+            ));
+        } else if !diff_result.field_changes.is_empty()
+            && diff_result
+                .field_changes
+                .iter()
+                .all(|c| c.change == FieldChangeKind::Reordered)
+        {
+            // Only the order of list elements differs, which still counts as a change
+            let paths: String = diff_result
+                .field_changes
+                .iter()
+                .map(|c| format!("reordered: {}\n", c.path))
+                .collect();
+            let name = format!("{} (reordered)", diff_result.resource_name);
+            Pretty::print(paths, Some(&name), term_width);
+        } else {
+            // No changes
+            logger.lock().unwrap().log_info(format!(
+                "No changes in: {:?} {:?} {:?}\n",
+                diff_result.api_version, diff_result.kind, diff_result.resource_name
+            ));
+        }
+    }
+}
+
+/// Print the statistics of all targets as a table.
+///
+/// With `list_resources` the resources that differ or failed are listed below it.
+fn print_summary(results: &[TargetResult], list_resources: bool, term_width: Option<usize>) {
+    if results.is_empty() {
+        return;
+    }
+    let summary = Summary::new(results);
+    let width = summary
+        .targets
+        .iter()
+        .map(|t| t.target.len())
+        .chain(["TARGET".len()])
+        .max()
+        .unwrap_or_default();
+
+    Pretty::print_path("Summary".to_string(), term_width);
+    println!(
+        "{:<width$}  {:>7}  {:>7}  {:>5}  {:>7}  {:>6}  {:>6}  {:>6}",
+        "TARGET", "CHECKED", "CHANGED", "NEW", "REMOVED", "ERRORS", "+LINES", "-LINES"
+    );
+    for target in summary.targets.iter().chain([&summary.total]) {
+        println!(
+            "{:<width$}  {:>7}  {:>7}  {:>5}  {:>7}  {:>6}  {:>6}  {:>6}",
+            target.target,
+            target.checked,
+            target.changed,
+            target.added,
+            target.removed,
+            target.errors,
+            target.lines_added,
+            target.lines_removed
+        );
+    }
+
+    if !list_resources {
+        return;
+    }
+    println!();
+    for result in results {
+        if let Some(ref error) = result.build_error {
+            println!("{} {}: {}", "error".red(), result.target, error);
+        }
+        for diff_result in &result.results {
+            let status = match diff_result.status {
+                ChangeStatus::Unchanged => continue,
+                ChangeStatus::Changed => "changed".yellow(),
+                ChangeStatus::Added => "new".green(),
+                ChangeStatus::Removed => "removed".red(),
+                ChangeStatus::Error => "error".red(),
+                ChangeStatus::Skipped => "skipped".yellow(),
+            };
+            let namespace = diff_result
+                .namespace
+                .as_deref()
+                .map(|ns| format!(" -n {}", ns))
+                .unwrap_or_default();
+            println!(
+                "{} {}: {}/{}{}",
+                status, result.target, diff_result.kind, diff_result.resource_name, namespace
+            );
+        }
+    }
+}
+
+/// List the changed fields of a three-way diff by origin
+///
+/// Logged as a warning if any field conflicts with another field manager.
+fn print_change_origins(diff_result: &DiffResult, logger: &Arc<Mutex<Logger>>) {
+    let lines: Vec<String> = diff_result
+        .field_changes
+        .iter()
+        .filter_map(|change| {
+            let label = match change.origin? {
+                ChangeOrigin::Local => "local",
+                ChangeOrigin::Drift => "drift",
+                ChangeOrigin::Conflict => "conflict",
+            };
+            Some(format!("{}: {}\n", label, change.path))
+        })
+        .collect();
+    if lines.is_empty() {
+        return;
+    }
+
+    let logger = logger.lock().unwrap();
+    let conflicts = diff_result
+        .field_changes
+        .iter()
+        .any(|change| change.origin == Some(ChangeOrigin::Conflict));
+    if conflicts {
+        logger.log_warning(lines.concat());
+    } else {
+        logger.log_info(lines.concat());
+    }
+}
@@ -0,0 +1,374 @@
+//! Field ownership from `metadata.managedFields`.
+//!
+//! The API server records which field manager set which fields of an object.
+//! This module reads those ownership sets, answers who owns a field and can
+//! strip an object down to the fields owned by a set of managers, so that
+//! fields set by controllers (HPA replicas, injected sidecars, defaults) stay
+//! out of the diff.
+
+use serde_json::Value;
+
+use crate::field_diff::{scalar_string, FieldPath, PathSegment};
+
+/// Field manager used for the server-side dry-run of the local manifests
+pub const KUBEDIFF_FIELD_MANAGER: &str = "kubediff";
+
+/// Field manager of `kubectl apply --server-side`
+pub const KUBECTL_FIELD_MANAGER: &str = "kubectl";
+
+/// Field managers treated as ours when none are configured
+pub const DEFAULT_FIELD_MANAGERS: &[&str] = &[
+    KUBEDIFF_FIELD_MANAGER,
+    KUBECTL_FIELD_MANAGER,
+    "kubectl-client-side-apply",
+];
+
+/// Fields identifying an object, kept when stripping it to owned fields
+const IDENTITY_FIELDS: &[&[&str]] = &[
+    &["apiVersion"],
+    &["kind"],
+    &["metadata", "name"],
+    &["metadata", "namespace"],
+];
+
+/// The fields owned by each field manager of an object
+#[derive(Debug, Clone, Default)]
+pub struct ManagedFields(Vec<(String, Vec<FieldPath>)>);
+
+impl ManagedFields {
+    /// Read the managed fields of an unfiltered object, None if it has none
+    pub fn from_object(object: &Value) -> Option<Self> {
+        let managed: Vec<(String, Vec<FieldPath>)> = object["metadata"]["managedFields"]
+            .as_array()?
+            .iter()
+            .filter_map(|entry| {
+                let manager = entry["manager"].as_str()?.to_string();
+                let mut owned = vec![];
+                owned_paths(&FieldPath::default(), &entry["fieldsV1"], &mut owned);
+                Some((manager, owned))
+            })
+            .collect();
+        (!managed.is_empty()).then_some(ManagedFields(managed))
+    }
+
+    /// Whether a field is owned by one of our managers and by any other manager
+    ///
+    /// `managers` are the field managers treated as ours, empty for the defaults.
+    pub fn owners_of(&self, path: &FieldPath, managers: &[String]) -> (bool, bool) {
+        let (mut ours, mut others) = (false, false);
+        for (manager, owned) in &self.0 {
+            if owned.iter().any(|p| p.covers(path)) {
+                if is_our_manager(manager, managers) {
+                    ours = true;
+                } else {
+                    others = true;
+                }
+            }
+        }
+        (ours, others)
+    }
+
+    /// Paths owned by the managers matching `filter`
+    pub fn owned_by(&self, filter: impl Fn(&str) -> bool) -> Vec<FieldPath> {
+        self.0
+            .iter()
+            .filter(|(manager, _)| filter(manager))
+            .flat_map(|(_, owned)| owned.iter().cloned())
+            .collect()
+    }
+}
+
+/// Paths owned by our managers on either side of a diff, None if neither side has managed fields
+///
+/// On the local side, the dry-run result, the fields set by the manifest are
+/// owned by [`KUBEDIFF_FIELD_MANAGER`] and always included.
+pub fn owned_fields(
+    live: Option<&Value>,
+    local: Option<&Value>,
+    managers: &[String],
+) -> Option<Vec<FieldPath>> {
+    let live_fields = live.and_then(ManagedFields::from_object);
+    let local_fields = local.and_then(ManagedFields::from_object);
+    if live_fields.is_none() && local_fields.is_none() {
+        return None;
+    }
+
+    let mut owned = vec![];
+    if let Some(fields) = live_fields {
+        owned.extend(fields.owned_by(|m| is_our_manager(m, managers)));
+    }
+    if let Some(fields) = local_fields {
+        owned.extend(
+            fields.owned_by(|m| m == KUBEDIFF_FIELD_MANAGER || is_our_manager(m, managers)),
+        );
+    }
+    Some(owned)
+}
+
+/// Whether a field manager is one of ours
+///
+/// `managers` are the configured managers, empty for [`DEFAULT_FIELD_MANAGERS`].
+pub fn is_our_manager(manager: &str, managers: &[String]) -> bool {
+    if managers.is_empty() {
+        DEFAULT_FIELD_MANAGERS.contains(&manager)
+    } else {
+        managers.iter().any(|m| m == manager)
+    }
+}
+
+/// The field manager the manifests are deployed with
+///
+/// That is the first of the configured `managers`, or [`KUBECTL_FIELD_MANAGER`].
+pub fn apply_manager(managers: &[String]) -> &str {
+    managers
+        .first()
+        .map_or(KUBECTL_FIELD_MANAGER, String::as_str)
+}
+
+/// Remove every field of `value` that isn't covered by one of the `owned` paths.
+///
+/// The apiVersion, kind, name and namespace are always kept.
+pub fn retain_owned(value: &mut Value, owned: &[FieldPath]) {
+    let identity: Vec<FieldPath> = IDENTITY_FIELDS
+        .iter()
+        .map(|fields| {
+            FieldPath(
+                fields
+                    .iter()
+                    .map(|f| PathSegment::Field(f.to_string()))
+                    .collect(),
+            )
+        })
+        .collect();
+    let paths: Vec<&[PathSegment]> = owned
+        .iter()
+        .chain(identity.iter())
+        .map(|p| p.0.as_slice())
+        .collect();
+    retain(value, &paths);
+}
+
+/// Keep the parts of `value` covered by `paths`, relative to `value`.
+///
+/// Returns false if nothing of `value` is owned and it should be removed.
+fn retain(value: &mut Value, paths: &[&[PathSegment]]) -> bool {
+    if paths.iter().any(|p| p.is_empty()) {
+        return true;
+    }
+
+    match value {
+        Value::Object(obj) => {
+            obj.retain(|key, child| {
+                let rest = rest_matching(
+                    paths,
+                    |segment| matches!(segment, PathSegment::Field(name) if name == key),
+                );
+                !rest.is_empty() && retain(child, &rest)
+            });
+            !obj.is_empty()
+        }
+        Value::Array(arr) => {
+            let mut index = 0;
+            arr.retain_mut(|element| {
+                let i = index;
+                index += 1;
+                let mut key_fields = vec![];
+                let rest = rest_matching(paths, |segment| match segment {
+                    PathSegment::Index(owned) => *owned == i,
+                    PathSegment::Key(pairs) => {
+                        let matched = pairs.iter().all(|(k, v)| {
+                            element.get(k).map(scalar_string).as_deref() == Some(v.as_str())
+                        });
+                        if matched {
+                            key_fields.extend(pairs.iter().map(|(k, _)| k.clone()));
+                        }
+                        matched
+                    }
+                    PathSegment::Field(_) => false,
+                });
+                let keys: Vec<(String, Value)> = key_fields
+                    .into_iter()
+                    .filter_map(|k| element.get(&k).cloned().map(|v| (k, v)))
+                    .collect();
+                if rest.is_empty() || !retain(element, &rest) {
+                    return false;
+                }
+                // Keep the merge keys so the element can still be matched
+                if let Some(obj) = element.as_object_mut() {
+                    for (k, v) in keys {
+                        obj.entry(k).or_insert(v);
+                    }
+                }
+                true
+            });
+            !arr.is_empty()
+        }
+        // Only fields below a scalar are owned, which can't be
+        _ => false,
+    }
+}
+
+/// The remainders of the paths whose first segment matches
+fn rest_matching<'a>(
+    paths: &[&'a [PathSegment]],
+    mut matches: impl FnMut(&PathSegment) -> bool,
+) -> Vec<&'a [PathSegment]> {
+    paths
+        .iter()
+        .filter_map(|p| p.split_first())
+        .filter(|(first, _)| matches(first))
+        .map(|(_, rest)| rest)
+        .collect()
+}
+
+/// Collect the paths owned in a `fieldsV1` set, e.g. `{"f:spec":{"f:replicas":{}}}`
+///
+/// List elements keyed by `k:` become key segments, `i:` index segments.
+/// Owning a `v:` set member owns the enclosing list.
+fn owned_paths(path: &FieldPath, fields: &Value, owned: &mut Vec<FieldPath>) {
+    let Some(fields) = fields.as_object().filter(|f| !f.is_empty()) else {
+        owned.push(path.clone());
+        return;
+    };
+
+    for (key, children) in fields {
+        let segment = if let Some(name) = key.strip_prefix("f:") {
+            PathSegment::Field(name.to_string())
+        } else if let Some(json) = key.strip_prefix("k:") {
+            let Ok(Value::Object(pairs)) = serde_json::from_str::<Value>(json) else {
+                continue;
+            };
+            PathSegment::Key(
+                pairs
+                    .iter()
+                    .map(|(k, v)| (k.clone(), scalar_string(v)))
+                    .collect(),
+            )
+        } else if let Some(Ok(index)) = key.strip_prefix("i:").map(str::parse) {
+            PathSegment::Index(index)
+        } else if key == "." {
+            // Only marks that the field itself exists, its children are listed separately
+            continue;
+        } else {
+            owned.push(path.clone());
+            continue;
+        };
+        owned_paths(&path.child(segment), children, owned);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn object() -> Value {
+        json!({
+            "apiVersion": "apps/v1",
+            "kind": "Deployment",
+            "metadata": {
+                "name": "web",
+                "managedFields": [
+                    {
+                        "manager": "kubectl",
+                        "fieldsV1": {
+                            "f:spec": {
+                                "f:template": {"f:spec": {"f:containers": {
+                                    "k:{\"name\":\"app\"}": {".": {}, "f:image": {}}
+                                }}}
+                            }
+                        }
+                    },
+                    {
+                        "manager": "hpa",
+                        "fieldsV1": {"f:spec": {"f:replicas": {}}}
+                    }
+                ]
+            },
+            "spec": {
+                "replicas": 3,
+                "template": {"spec": {"containers": [
+                    {"name": "app", "image": "web:2", "resources": {}},
+                    {"name": "sidecar", "image": "proxy:1"}
+                ]}}
+            }
+        })
+    }
+
+    fn path(fields: &[&str]) -> FieldPath {
+        FieldPath(
+            fields
+                .iter()
+                .map(|f| PathSegment::Field(f.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn reads_owned_paths_without_the_field_markers() {
+        let managed = ManagedFields::from_object(&object()).unwrap();
+        let owned: Vec<String> = managed
+            .owned_by(|m| m == "kubectl")
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(owned, vec!["spec.template.spec.containers[name=app].image"]);
+    }
+
+    #[test]
+    fn objects_without_managed_fields_have_none() {
+        assert!(ManagedFields::from_object(&json!({"metadata": {"name": "web"}})).is_none());
+    }
+
+    #[test]
+    fn finds_our_and_other_owners_of_a_field() {
+        let managed = ManagedFields::from_object(&object()).unwrap();
+        let replicas = path(&["spec", "replicas"]);
+        assert_eq!(managed.owners_of(&replicas, &[]), (false, true));
+        assert_eq!(
+            managed.owners_of(&replicas, &["hpa".to_string()]),
+            (true, false)
+        );
+        assert_eq!(
+            managed.owners_of(&path(&["spec", "paused"]), &[]),
+            (false, false)
+        );
+    }
+
+    #[test]
+    fn retains_owned_fields_and_identity() {
+        let mut value = object();
+        let owned = ManagedFields::from_object(&value)
+            .unwrap()
+            .owned_by(|m| m == "kubectl");
+        retain_owned(&mut value, &owned);
+        assert_eq!(
+            value,
+            json!({
+                "apiVersion": "apps/v1",
+                "kind": "Deployment",
+                "metadata": {"name": "web"},
+                "spec": {"template": {"spec": {"containers": [
+                    {"name": "app", "image": "web:2"}
+                ]}}}
+            })
+        );
+    }
+
+    #[test]
+    fn applies_as_the_first_configured_manager() {
+        assert_eq!(apply_manager(&[]), KUBECTL_FIELD_MANAGER);
+        assert_eq!(
+            apply_manager(&["argocd".to_string(), "kubectl".to_string()]),
+            "argocd"
+        );
+    }
+
+    #[test]
+    fn default_managers_are_used_when_none_are_configured() {
+        assert!(is_our_manager(KUBEDIFF_FIELD_MANAGER, &[]));
+        assert!(!is_our_manager("hpa", &[]));
+        assert!(!is_our_manager("kubectl", &["argocd".to_string()]));
+    }
+}
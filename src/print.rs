@@ -21,13 +21,13 @@ impl Pretty {
     pub fn print_path(string: String, term_width: Option<usize>) {
         let mut printer = PrettyPrinter::new();
         printer
-            .input(Input::from_bytes(&string.as_bytes()))
+            .input(Input::from_bytes(string.as_bytes()))
             .header(false)
             .grid(true)
             .language("syslog")
             .theme("OneHalfDark");
-        if term_width.is_some() {
-            printer.term_width(term_width.unwrap());
+        if let Some(width) = term_width {
+            printer.term_width(width);
         }
         printer.print().unwrap();
     }
@@ -35,14 +35,14 @@ impl Pretty {
     pub fn print_info(string: String, term_width: Option<usize>) {
         let mut printer = PrettyPrinter::new();
         printer
-            .input(Input::from_bytes(&string.as_bytes()))
+            .input(Input::from_bytes(string.as_bytes()))
             .header(false)
             .grid(false)
             .language("yaml")
             .theme("OneHalfDark");
 
-        if term_width.is_some() {
-            printer.term_width(term_width.unwrap());
+        if let Some(width) = term_width {
+            printer.term_width(width);
         }
         printer.print().unwrap();
     }
@@ -50,14 +50,14 @@ impl Pretty {
     pub fn print_warning(string: String, term_width: Option<usize>) {
         let mut printer = PrettyPrinter::new();
         printer
-            .input(Input::from_bytes(&string.as_bytes()))
+            .input(Input::from_bytes(string.as_bytes()))
             .header(false)
             .grid(true)
             .language("log")
             .theme("OneHalfDark");
 
-        if term_width.is_some() {
-            printer.term_width(term_width.unwrap());
+        if let Some(width) = term_width {
+            printer.term_width(width);
         }
         printer.print().unwrap();
     }
@@ -72,10 +72,10 @@ impl Pretty {
             .language("log")
             .theme("Monokai Extended Bright")
             .paging_mode(PagingMode::Never)
-            .input(Input::from_bytes(&string.as_bytes()));
+            .input(Input::from_bytes(string.as_bytes()));
 
-        if term_width.is_some() {
-            printer.term_width(term_width.unwrap());
+        if let Some(width) = term_width {
+            printer.term_width(width);
         }
         printer.print().unwrap();
     }
@@ -83,7 +83,7 @@ impl Pretty {
         let mut printer = PrettyPrinter::new();
         printer
             .input(
-                Input::from_bytes(&string.as_bytes())
+                Input::from_bytes(string.as_bytes())
                     .name(filename.unwrap_or("Diff.yaml"))
                     .kind("Name"),
             )
@@ -95,8 +95,8 @@ impl Pretty {
             .theme("gruvbox-dark")
             .paging_mode(PagingMode::Never);
 
-        if term_width.is_some() {
-            printer.term_width(term_width.unwrap());
+        if let Some(width) = term_width {
+            printer.term_width(width);
         }
         printer.print().unwrap();
     }
@@ -1,13 +1,30 @@
 use futures::future::join_all;
-use serde::Deserialize;
-use serde_yaml::Value;
+use kube::ResourceExt;
+use serde::Serialize;
+use serde_json::Value;
 
 use std::{collections::HashSet, env};
 
-use crate::{commands::Commands, kube_client::KubeClient, settings::Settings};
+use crate::{
+    commands::{BuildOptions, Commands, ResourceDiff},
+    compare::parse_documents,
+    enums::{ChangeStatus, Normalization},
+    error::KubediffError,
+    field_diff::FieldChange,
+    filter::IgnoreRule,
+    helm,
+    kube_client::{missing_namespace, ApplyConflict, KubeClient},
+    resource::{is_owned, ResourceId},
+    selector::ResourceSelector,
+    settings::Settings,
+};
+
+/// Label that ties objects to an ApplySet inventory
+const APPLYSET_PART_OF_LABEL: &str = "applyset.kubernetes.io/part-of";
 
 /// Result of diffing a single Kubernetes resource
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct DiffResult {
     /// The target path that was processed
     pub target: String,
@@ -17,21 +34,190 @@ pub struct DiffResult {
     pub api_version: String,
     /// The kind of the resource (Deployment, Service, etc.)
     pub kind: String,
+    /// The namespace of the resource as written in the manifest
+    pub namespace: Option<String>,
+    /// Whether the resource is new, changed, unchanged or failed
+    pub status: ChangeStatus,
     /// The diff output if changes exist, None if no changes
     pub diff: Option<String>,
-    /// Error message if processing failed for this resource
-    pub error: Option<String>,
+    /// Fields that differ between the live and local resource
+    pub field_changes: Vec<FieldChange>,
+    /// Fields owned by other field managers that applying would take over
+    pub conflicts: Vec<ApplyConflict>,
+    /// How the local manifest was normalized, client-side results lack server defaults
+    pub normalization: Normalization,
+    /// Why processing failed for this resource
+    pub error: Option<KubediffError>,
+}
+
+impl DiffResult {
+    /// Result for a document that failed before its identity was known
+    pub(crate) fn failed(target: &str, error: KubediffError) -> Self {
+        DiffResult {
+            target: target.to_string(),
+            resource_name: "unknown".to_string(),
+            api_version: "unknown".to_string(),
+            kind: "unknown".to_string(),
+            namespace: None,
+            status: ChangeStatus::Error,
+            diff: None,
+            field_changes: vec![],
+            conflicts: vec![],
+            normalization: Normalization::None,
+            error: Some(error),
+        }
+    }
+
+    /// The result without its diff, for a resource that may only be missing
+    /// on one side because the manifests stopped parsing before it
+    pub(crate) fn skipped(self) -> Self {
+        DiffResult {
+            status: ChangeStatus::Skipped,
+            diff: None,
+            field_changes: vec![],
+            conflicts: vec![],
+            ..self
+        }
+    }
+
+    /// Result for an identified resource
+    pub(crate) fn for_resource(
+        target: &str,
+        id: &ResourceId,
+        diff: anyhow::Result<ResourceDiff>,
+    ) -> Self {
+        let mut result = DiffResult {
+            target: target.to_string(),
+            resource_name: id.name.clone(),
+            api_version: id.api_version.clone(),
+            kind: id.kind.clone(),
+            namespace: id.namespace.clone(),
+            status: ChangeStatus::Unchanged,
+            diff: None,
+            field_changes: vec![],
+            conflicts: vec![],
+            normalization: Normalization::None,
+            error: None,
+        };
+        match diff {
+            Ok(resource_diff) => {
+                result.status = resource_diff.status;
+                result.diff = resource_diff.diff;
+                result.field_changes = resource_diff.field_changes;
+                result.conflicts = resource_diff.conflicts;
+                result.normalization = resource_diff.normalization;
+            }
+            Err(e) => {
+                result.status = ChangeStatus::Error;
+                result.error = Some(e.into());
+            }
+        }
+        result
+    }
 }
 
 /// Result of processing a single target path
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct TargetResult {
     /// The target path that was processed
     pub target: String,
     /// Results for each resource in the target
     pub results: Vec<DiffResult>,
     /// Build errors that occurred before diffing (e.g., kustomize failures)
-    pub build_error: Option<String>,
+    pub build_error: Option<KubediffError>,
+}
+
+/// Options controlling how targets are processed
+#[derive(Debug, Clone, Default)]
+pub struct DiffOptions {
+    /// Report live objects that are missing from the manifests as removed
+    pub prune: Option<PruneOptions>,
+    /// Fields to strip from both sides before diffing
+    pub ignore_rules: Vec<IgnoreRule>,
+    /// Show Secret `data` and `stringData` values instead of masking them
+    pub show_secrets: bool,
+    /// Mask Secret values as SHA-256 fingerprints instead of `***`
+    ///
+    /// Fingerprints are unsalted, weak values can be recovered by guessing.
+    pub secret_fingerprints: bool,
+    /// Options for building the manifests of each target
+    pub build: BuildOptions,
+    /// Classify field changes as local, drift or conflict against what was last applied
+    pub three_way: bool,
+    /// Field managers treated as ours, defaults to kubediff and kubectl
+    pub field_managers: Vec<String>,
+    /// Only compare fields owned by our field managers according to `managedFields`
+    pub owned_only: bool,
+    /// Dry-run without forcing field ownership and report conflicts with other managers
+    ///
+    /// The dry-run is done as the first of `field_managers`, or as kubectl.
+    pub report_conflicts: bool,
+    /// Compare client-side without a server-side dry-run, as done when it's forbidden
+    pub client_side: bool,
+    /// Only diff the resources of each target matched by this selector
+    pub selector: ResourceSelector,
+    /// Namespace of manifests without one when comparing offline, `default` if unset.
+    /// With a cluster, the client's default namespace is used instead.
+    pub default_namespace: Option<String>,
+}
+
+impl DiffOptions {
+    /// Namespace of manifests without one when no cluster is involved
+    pub(crate) fn offline_namespace(&self) -> &str {
+        self.default_namespace.as_deref().unwrap_or("default")
+    }
+}
+
+/// Selects the live objects that belong to a target for prune detection
+///
+/// Like `kubectl apply --prune`, only live objects of the kinds in the
+/// rendered manifests are considered, so objects that controllers create
+/// with copied labels, e.g. Endpoints, aren't reported as removed. A
+/// selector or an ApplySet is required.
+#[derive(Debug, Clone, Default)]
+pub struct PruneOptions {
+    /// Label selector matching the objects managed by the target
+    pub selector: Option<String>,
+    /// ApplySet id, matches objects labeled `applyset.kubernetes.io/part-of=<id>`
+    pub applyset: Option<String>,
+}
+
+impl PruneOptions {
+    /// Combined label selector used to list live objects
+    pub fn label_selector(&self) -> String {
+        let mut selectors = vec![];
+        if let Some(selector) = &self.selector {
+            selectors.push(selector.clone());
+        }
+        if let Some(applyset) = &self.applyset {
+            selectors.push(format!("{}={}", APPLYSET_PART_OF_LABEL, applyset));
+        }
+        selectors.join(",")
+    }
+}
+
+impl TargetResult {
+    /// Whether any resource in the target differs from the cluster, including
+    /// lists that only changed their order
+    pub fn has_changes(&self) -> bool {
+        self.results.iter().any(|r| {
+            matches!(
+                r.status,
+                ChangeStatus::Changed | ChangeStatus::Added | ChangeStatus::Removed
+            )
+        })
+    }
+
+    /// Whether applying any resource would take over fields owned by another manager
+    pub fn has_conflicts(&self) -> bool {
+        self.results.iter().any(|r| !r.conflicts.is_empty())
+    }
+
+    /// Whether building the target or processing any of its resources failed
+    pub fn has_errors(&self) -> bool {
+        self.build_error.is_some() || self.results.iter().any(|r| r.error.is_some())
+    }
 }
 
 pub struct Process;
@@ -60,53 +246,130 @@ impl Process {
 
     /// Process a single target and return structured results
     pub async fn process_target(client: &KubeClient, target: &str) -> TargetResult {
+        Self::process_target_with_options(client, target, &DiffOptions::default()).await
+    }
+
+    /// Process a single target with the given options and return structured results
+    pub async fn process_target_with_options(
+        client: &KubeClient,
+        target: &str,
+        options: &DiffOptions,
+    ) -> TargetResult {
         // Try to get the build output
-        let build = match Commands::get_build(target) {
+        let build = match Commands::get_build_with_options(target, &options.build) {
             Ok(b) => b,
             Err(e) => {
                 return TargetResult {
                     target: target.to_string(),
                     results: vec![],
-                    build_error: Some(e.to_string()),
+                    build_error: Some(KubediffError::build_failed(e)),
                 };
             }
         };
 
-        // Parse YAML documents, collecting any deserialization errors
+        let namespace = helm::release_namespace(target, &options.build.helm);
+        Self::process_documents(client, target, &build, options, namespace).await
+    }
+
+    /// Process an in-memory multi-document YAML string and return structured results
+    ///
+    /// `source_name` is reported as the target of the results, e.g. "stdin".
+    pub async fn process_manifests(
+        client: &KubeClient,
+        source_name: &str,
+        yaml: &str,
+    ) -> TargetResult {
+        Self::process_manifests_with_options(client, source_name, yaml, &DiffOptions::default())
+            .await
+    }
+
+    /// Process an in-memory multi-document YAML string with the given options
+    pub async fn process_manifests_with_options(
+        client: &KubeClient,
+        source_name: &str,
+        yaml: &str,
+        options: &DiffOptions,
+    ) -> TargetResult {
+        Self::process_documents(client, source_name, yaml, options, None).await
+    }
+
+    /// Diff the documents of a target, `namespace` overrides the client's
+    /// default namespace for namespaced resources without one
+    ///
+    /// A document that fails to parse drops every document after it, a YAML
+    /// syntax error every document of the target, see [`parse_documents`].
+    /// Live objects that prune would report as removed are then reported as
+    /// skipped instead.
+    async fn process_documents(
+        client: &KubeClient,
+        source_name: &str,
+        yaml: &str,
+        options: &DiffOptions,
+        namespace: Option<&str>,
+    ) -> TargetResult {
+        // Parse YAML documents, collecting any deserialization errors. Every
+        // document is kept here, unselected ones can still be prerequisites.
         let mut deserialization_errors: Vec<DiffResult> = vec![];
-        let documents: Vec<Value> = serde_yaml::Deserializer::from_str(build.as_str())
-            .filter_map(|document| {
-                let v_result = Value::deserialize(document);
-                match v_result {
-                    Ok(v) => Some(v),
-                    Err(error) => {
-                        deserialization_errors.push(DiffResult {
-                            target: target.to_string(),
-                            resource_name: "unknown".to_string(),
-                            api_version: "unknown".to_string(),
-                            kind: "unknown".to_string(),
-                            diff: None,
-                            error: Some(error.to_string()),
-                        });
-                        None
-                    }
-                }
-            })
-            .collect();
+        let (documents, truncated) = parse_documents(
+            source_name,
+            yaml,
+            &ResourceSelector::default(),
+            client.default_namespace(),
+            &mut deserialization_errors,
+        );
+        let mut documents: Vec<Value> = documents.into_values().collect();
 
-        // Process documents concurrently using async
-        let futures: Vec<_> = documents
+        if let Some(namespace) = namespace {
+            let futures: Vec<_> = documents
+                .iter_mut()
+                .map(|v| set_default_namespace(client, v, namespace))
+                .collect();
+            join_all(futures).await;
+        }
+
+        // CRDs and Namespaces go first, other resources of the target may depend on them.
+        // Resources that aren't selected are skipped but can still be prerequisites.
+        let prerequisites = Prerequisites::from_documents(&documents);
+        let (first, rest): (Vec<&Value>, Vec<&Value>) = documents
             .iter()
-            .map(|v| process_single_document(client, target, v))
-            .collect();
+            .filter(|v| {
+                options
+                    .selector
+                    .matches_manifest(v, Some(client.default_namespace()))
+            })
+            .partition(|v| is_prerequisite(v));
 
-        let mut results: Vec<DiffResult> = join_all(futures).await;
+        // Process documents concurrently using async
+        let mut results: Vec<DiffResult> = vec![];
+        for batch in [first, rest] {
+            let futures: Vec<_> = batch
+                .into_iter()
+                .map(|v| process_single_document(client, source_name, v, options, &prerequisites))
+                .collect();
+            results.extend(join_all(futures).await);
+        }
 
         // Add any deserialization errors to the results
         results.extend(deserialization_errors);
 
+        // Report live objects that are no longer rendered
+        if let Some(prune) = &options.prune {
+            let rendered: Vec<ResourceId> = documents
+                .iter()
+                .filter_map(ResourceId::from_value)
+                .collect();
+            let pruned = prune_resources(client, source_name, &rendered, prune, options).await;
+            results.extend(pruned.into_iter().map(|result| {
+                if truncated && result.status == ChangeStatus::Removed {
+                    result.skipped()
+                } else {
+                    result
+                }
+            }));
+        }
+
         TargetResult {
-            target: target.to_string(),
+            target: source_name.to_string(),
             results,
             build_error: None,
         }
@@ -116,17 +379,34 @@ impl Process {
     pub async fn process_targets(
         client: &KubeClient,
         targets: HashSet<String>,
+    ) -> Vec<TargetResult> {
+        Self::process_targets_with_options(client, targets, &DiffOptions::default()).await
+    }
+
+    /// Process multiple targets with the given options and return all results
+    pub async fn process_targets_with_options(
+        client: &KubeClient,
+        targets: HashSet<String>,
+        options: &DiffOptions,
     ) -> Vec<TargetResult> {
         let futures: Vec<_> = targets
             .into_iter()
-            .map(|target| async move { Self::process_target(client, &target).await })
+            .map(|target| async move {
+                Self::process_target_with_options(client, &target, options).await
+            })
             .collect();
 
         join_all(futures).await
     }
 }
 
-async fn process_single_document(client: &KubeClient, target: &str, v: &Value) -> DiffResult {
+async fn process_single_document(
+    client: &KubeClient,
+    target: &str,
+    v: &Value,
+    options: &DiffOptions,
+    prerequisites: &Prerequisites,
+) -> DiffResult {
     let string = serde_yaml::to_string(&v).unwrap();
     let resource_name = v["metadata"]["name"]
         .as_str()
@@ -134,26 +414,191 @@ async fn process_single_document(client: &KubeClient, target: &str, v: &Value) -
         .to_string();
     let api_version = v["apiVersion"].as_str().unwrap_or("unknown").to_string();
     let kind = v["kind"].as_str().unwrap_or("unknown").to_string();
+    let namespace = v["metadata"]["namespace"].as_str().map(str::to_string);
 
-    match Commands::get_diff(client, &string).await {
-        Ok(diff) => {
-            let diff_option = if diff.is_empty() { None } else { Some(diff) };
-            DiffResult {
-                target: target.to_string(),
-                resource_name,
+    match Commands::get_diff_with_options(client, &string, options).await {
+        Ok(resource_diff) => DiffResult {
+            target: target.to_string(),
+            resource_name,
+            api_version,
+            kind,
+            namespace,
+            status: resource_diff.status,
+            diff: resource_diff.diff,
+            field_changes: resource_diff.field_changes,
+            conflicts: resource_diff.conflicts,
+            normalization: resource_diff.normalization,
+            error: None,
+        },
+        Err(e) if prerequisites.provide(&e) => {
+            // The server can't know about the resource yet, show the manifest as it is
+            let id = format!("{}/{}", kind, resource_name);
+            let local = serde_json::to_value(v).ok();
+            let diff = Commands::diff_resources(&id, None, local, options).map(|mut diff| {
+                diff.normalization = Normalization::Unvalidated;
+                diff
+            });
+            let id = ResourceId {
                 api_version,
                 kind,
-                diff: diff_option,
-                error: None,
-            }
+                namespace,
+                name: resource_name,
+            };
+            DiffResult::for_resource(target, &id, diff)
         }
         Err(e) => DiffResult {
             target: target.to_string(),
             resource_name,
             api_version,
             kind,
+            namespace,
+            status: ChangeStatus::Error,
             diff: None,
-            error: Some(e.to_string()),
+            field_changes: vec![],
+            conflicts: vec![],
+            normalization: Normalization::None,
+            error: Some(e.into()),
         },
     }
 }
+
+/// Set the namespace of a namespaced resource that doesn't specify one
+async fn set_default_namespace(client: &KubeClient, v: &mut Value, namespace: &str) {
+    if !v["metadata"]["namespace"].is_null() {
+        return;
+    }
+    let (Some(api_version), Some(kind)) = (v["apiVersion"].as_str(), v["kind"].as_str()) else {
+        return;
+    };
+    // Unknown types are left alone, processing them reports the error
+    if let Ok(Some(true)) = client.is_namespaced(api_version, kind).await {
+        if let Some(metadata) = v.get_mut("metadata").and_then(Value::as_object_mut) {
+            metadata.insert("namespace".to_string(), namespace.into());
+        }
+    }
+}
+
+/// Whether a document creates something other resources can depend on
+fn is_prerequisite(v: &Value) -> bool {
+    matches!(
+        v["kind"].as_str(),
+        Some("CustomResourceDefinition") | Some("Namespace")
+    )
+}
+
+/// Resource types and namespaces created by the documents of a single target
+#[derive(Debug, Default)]
+struct Prerequisites {
+    /// (group, kind) of the custom resources defined by CRDs
+    kinds: HashSet<(String, String)>,
+    namespaces: HashSet<String>,
+}
+
+impl Prerequisites {
+    fn from_documents(documents: &[Value]) -> Self {
+        let mut prerequisites = Prerequisites::default();
+        for v in documents {
+            match v["kind"].as_str() {
+                Some("CustomResourceDefinition") => {
+                    let spec = &v["spec"];
+                    if let (Some(group), Some(kind)) =
+                        (spec["group"].as_str(), spec["names"]["kind"].as_str())
+                    {
+                        prerequisites
+                            .kinds
+                            .insert((group.to_string(), kind.to_string()));
+                    }
+                }
+                Some("Namespace") => {
+                    if let Some(name) = v["metadata"]["name"].as_str() {
+                        prerequisites.namespaces.insert(name.to_string());
+                    }
+                }
+                _ => {}
+            }
+        }
+        prerequisites
+    }
+
+    /// Whether an error is caused by a CRD or Namespace that only this target creates
+    fn provide(&self, error: &anyhow::Error) -> bool {
+        if let Some(KubediffError::UnknownResourceType { api_version, kind }) =
+            error.downcast_ref::<KubediffError>()
+        {
+            let group = match api_version.rsplit_once('/') {
+                Some((group, _)) => group,
+                None => "",
+            };
+            return self.kinds.contains(&(group.to_string(), kind.clone()));
+        }
+        missing_namespace(error).is_some_and(|ns| self.namespaces.contains(ns))
+    }
+}
+
+/// List live objects selected by the prune options that are not part of the rendered set
+async fn prune_resources(
+    client: &KubeClient,
+    target: &str,
+    rendered: &[ResourceId],
+    prune: &PruneOptions,
+    options: &DiffOptions,
+) -> Vec<DiffResult> {
+    let label_selector = prune.label_selector();
+    if label_selector.is_empty() {
+        let error =
+            anyhow::anyhow!("Prune needs a label selector or an ApplySet, not the whole cluster");
+        return vec![DiffResult::failed(target, error.into())];
+    }
+
+    let rendered_kind = |group: &str, kind: &str| {
+        rendered
+            .iter()
+            .any(|id| id.group() == group && id.kind == kind)
+    };
+    let objects = match client
+        .list_resources_of(&label_selector, rendered_kind)
+        .await
+    {
+        Ok(objects) => objects,
+        Err(e) => {
+            let error = e.context("Failed to list resources for prune");
+            return vec![DiffResult::failed(target, error.into())];
+        }
+    };
+
+    let mut removed: Vec<(ResourceId, DiffResult)> = objects
+        .iter()
+        .filter(|obj| !is_owned(obj))
+        .filter_map(|obj| ResourceId::from_object(obj).map(|id| (id, obj)))
+        .filter(|(id, obj)| {
+            let namespace = id.namespace.as_deref();
+            (options.selector).matches(&id.kind, &id.name, namespace, obj.labels())
+        })
+        .filter(|(id, _)| !is_rendered(rendered, id, client.default_namespace()))
+        .map(|(id, obj)| {
+            let diff = Commands::get_deletion_diff(obj, options);
+            let result = DiffResult::for_resource(target, &id, diff);
+            (id, result)
+        })
+        .collect();
+
+    removed.sort_by(|(a, _), (b, _)| a.cmp(b));
+    removed.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Whether a live object is part of the rendered manifests
+///
+/// Manifests without a namespace are matched against the client's default namespace.
+fn is_rendered(rendered: &[ResourceId], live: &ResourceId, default_namespace: &str) -> bool {
+    rendered.iter().any(|id| {
+        let namespace = match (&id.namespace, &live.namespace) {
+            (None, Some(_)) => Some(default_namespace.to_string()),
+            (namespace, _) => namespace.clone(),
+        };
+        ResourceId {
+            namespace,
+            ..id.clone()
+        }
+        .same_object(live)
+    })
+}
@@ -0,0 +1,139 @@
+//! Machine-readable report of a kubediff run.
+//!
+//! The report wraps all collected [`TargetResult`]s in a versioned envelope
+//! so that tooling consuming the JSON or YAML output can detect schema changes.
+//! A [`Summary`] of per-target and overall statistics is included.
+
+use serde::Serialize;
+
+use crate::{enums::ChangeStatus, processor::TargetResult};
+
+/// Version of the serialized report schema.
+///
+/// Bumped whenever a field is renamed, removed or changes meaning.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Versioned collection of target results
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    /// Schema version of this report
+    pub schema_version: u32,
+    /// Statistics of all targets
+    pub summary: Summary,
+    /// Results for every processed target
+    pub targets: Vec<TargetResult>,
+}
+
+impl Report {
+    pub fn new(targets: Vec<TargetResult>) -> Self {
+        Report {
+            schema_version: SCHEMA_VERSION,
+            summary: Summary::new(&targets),
+            targets,
+        }
+    }
+
+    /// Serialize the report as pretty-printed JSON
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Serialize the report as YAML
+    pub fn to_yaml(&self) -> anyhow::Result<String> {
+        Ok(serde_yaml::to_string(self)?)
+    }
+}
+
+/// Statistics of a run, per target and overall
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Summary {
+    /// Statistics of every target, in the order they were processed
+    pub targets: Vec<TargetSummary>,
+    /// Sum over all targets
+    pub total: TargetSummary,
+}
+
+impl Summary {
+    pub fn new(results: &[TargetResult]) -> Self {
+        let targets: Vec<TargetSummary> = results.iter().map(TargetSummary::new).collect();
+        let mut total = TargetSummary {
+            target: "total".to_string(),
+            ..TargetSummary::default()
+        };
+        for target in &targets {
+            total.add(target);
+        }
+        Summary { targets, total }
+    }
+}
+
+/// Counts of resources by status and of diff lines of a single target
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TargetSummary {
+    pub target: String,
+    /// Resources that were diffed, including failed ones
+    pub checked: usize,
+    pub unchanged: usize,
+    pub changed: usize,
+    /// Resources that don't exist yet
+    pub added: usize,
+    /// Live objects missing from the manifests
+    pub removed: usize,
+    /// Failed resources, plus one if the target failed to build
+    pub errors: usize,
+    /// Resources not compared because the manifests failed to parse
+    pub skipped: usize,
+    pub lines_added: usize,
+    pub lines_removed: usize,
+}
+
+impl TargetSummary {
+    pub fn new(result: &TargetResult) -> Self {
+        let mut summary = TargetSummary {
+            target: result.target.clone(),
+            checked: result.results.len(),
+            errors: usize::from(result.build_error.is_some()),
+            ..TargetSummary::default()
+        };
+
+        for diff_result in &result.results {
+            match diff_result.status {
+                ChangeStatus::Unchanged => summary.unchanged += 1,
+                ChangeStatus::Changed => summary.changed += 1,
+                ChangeStatus::Added => summary.added += 1,
+                ChangeStatus::Removed => summary.removed += 1,
+                ChangeStatus::Error => summary.errors += 1,
+                ChangeStatus::Skipped => summary.skipped += 1,
+            }
+
+            // Skip the `---`/`+++` header, hunk lines start with `@@`, ` `, `+` or `-`
+            let lines = diff_result
+                .diff
+                .iter()
+                .flat_map(|diff| diff.lines().skip(2));
+            for line in lines {
+                if line.starts_with('+') {
+                    summary.lines_added += 1;
+                } else if line.starts_with('-') {
+                    summary.lines_removed += 1;
+                }
+            }
+        }
+        summary
+    }
+
+    fn add(&mut self, other: &TargetSummary) {
+        self.checked += other.checked;
+        self.unchanged += other.unchanged;
+        self.changed += other.changed;
+        self.added += other.added;
+        self.removed += other.removed;
+        self.errors += other.errors;
+        self.skipped += other.skipped;
+        self.lines_added += other.lines_added;
+        self.lines_removed += other.lines_removed;
+    }
+}
@@ -0,0 +1,80 @@
+//! Identity of Kubernetes resources.
+//!
+//! A [`ResourceId`] identifies an object by apiVersion, kind, namespace and
+//! name so that rendered manifests can be matched against live objects.
+
+use std::fmt;
+
+use kube::api::DynamicObject;
+use serde::Serialize;
+
+/// Identifies a single Kubernetes object
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceId {
+    pub api_version: String,
+    pub kind: String,
+    pub namespace: Option<String>,
+    pub name: String,
+}
+
+impl ResourceId {
+    /// Read the identity of a manifest, None if apiVersion, kind or name are missing
+    pub fn from_value(value: &serde_json::Value) -> Option<Self> {
+        Some(ResourceId {
+            api_version: value["apiVersion"].as_str()?.to_string(),
+            kind: value["kind"].as_str()?.to_string(),
+            namespace: value["metadata"]["namespace"].as_str().map(str::to_string),
+            name: value["metadata"]["name"].as_str()?.to_string(),
+        })
+    }
+
+    /// Read the identity of an object returned by the API server
+    pub fn from_object(object: &DynamicObject) -> Option<Self> {
+        let types = object.types.as_ref()?;
+        Some(ResourceId {
+            api_version: types.api_version.clone(),
+            kind: types.kind.clone(),
+            namespace: object.metadata.namespace.clone(),
+            name: object.metadata.name.clone()?,
+        })
+    }
+
+    /// The API group, empty for the core group
+    pub fn group(&self) -> &str {
+        match self.api_version.split_once('/') {
+            Some((group, _)) => group,
+            None => "",
+        }
+    }
+
+    /// Whether both ids refer to the same object, ignoring the API version
+    ///
+    /// The same object can be served under several versions of its group, so
+    /// live objects listed in the preferred version still match manifests
+    /// written against an older one.
+    pub fn same_object(&self, other: &ResourceId) -> bool {
+        self.group() == other.group()
+            && self.kind == other.kind
+            && self.namespace == other.namespace
+            && self.name == other.name
+    }
+}
+
+/// Whether the object is owned by another object, e.g. a ReplicaSet owned by a Deployment
+///
+/// Owned objects are managed by their owner and never appear in manifests.
+pub fn is_owned(object: &DynamicObject) -> bool {
+    object
+        .metadata
+        .owner_references
+        .as_ref()
+        .is_some_and(|refs| !refs.is_empty())
+}
+
+impl fmt::Display for ResourceId {
+    /// Formats as `Kind/name`, the header used in diffs
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.kind, self.name)
+    }
+}
@@ -0,0 +1,207 @@
+//! Masking of Secret data before diffing.
+//!
+//! Values under `data` and `stringData` of `v1/Secret` objects are replaced
+//! so that neither the diff output nor the structured results leak them.
+//! Masked values still show whether a key was added, removed or changed.
+
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+
+/// Secret fields holding sensitive values
+const SECRET_DATA_FIELDS: &[&str] = &["data", "stringData"];
+
+const MASK: &str = "***";
+const MASK_BEFORE: &str = "*** (before)";
+const MASK_AFTER: &str = "*** (after)";
+const DIGEST_PREFIX: &str = "sha256:";
+
+/// Whether the resource is a core Secret
+pub fn is_secret(value: &Value) -> bool {
+    value["apiVersion"].as_str() == Some("v1") && value["kind"].as_str() == Some("Secret")
+}
+
+/// Mask the values of a Secret on both sides of a diff.
+///
+/// Values equal on both sides are masked as `***`, values that differ as
+/// `*** (before)` and `*** (after)`. With `fingerprint` every value is
+/// instead replaced by a short SHA-256 fingerprint, which lets changes be
+/// correlated across runs without printing the value. Fingerprints are
+/// unsalted for that, so weak values can still be recovered by hashing
+/// guesses until one matches.
+///
+/// Pass `None` for `live` when the resource doesn't exist in the cluster.
+/// Resources that aren't Secrets are left untouched.
+pub fn mask_secret_data(live: Option<&mut Value>, local: &mut Value, fingerprint: bool) {
+    let mut empty = Value::Null;
+    let live = live.unwrap_or(&mut empty);
+    if !is_secret(local) && !is_secret(live) {
+        return;
+    }
+
+    for field in SECRET_DATA_FIELDS {
+        let live_data = live.get(*field).and_then(Value::as_object).cloned();
+        let local_data = local.get(*field).and_then(Value::as_object).cloned();
+
+        if let Some(data) = &live_data {
+            let masked = mask_map(data, local_data.as_ref(), fingerprint, MASK_BEFORE);
+            live[*field] = Value::Object(masked);
+        }
+        if let Some(data) = &local_data {
+            let masked = mask_map(data, live_data.as_ref(), fingerprint, MASK_AFTER);
+            local[*field] = Value::Object(masked);
+        }
+    }
+}
+
+/// Replace the values of a Secret with their salted SHA-256 digests, e.g. `sha256:9f86...`.
+///
+/// Used to store Secrets on disk without their values while still being able
+/// to tell whether a value changed. The `salt` is prepended to every value,
+/// so stored digests can't be matched against digests of guessed values
+/// without it. Values that are already digests are kept, so both sides of a
+/// diff can be passed through this before masking.
+pub fn digest_secret_data(value: &mut Value, salt: &str) {
+    if !is_secret(value) {
+        return;
+    }
+
+    for field in SECRET_DATA_FIELDS {
+        let Some(data) = value.get_mut(*field).and_then(Value::as_object_mut) else {
+            continue;
+        };
+        for entry in data.values_mut() {
+            if entry.as_str().is_some_and(|s| s.starts_with(DIGEST_PREFIX)) {
+                continue;
+            }
+            *entry = Value::String(format!("{}{}", DIGEST_PREFIX, salted_digest(entry, salt)));
+        }
+    }
+}
+
+fn mask_map(
+    data: &Map<String, Value>,
+    other: Option<&Map<String, Value>>,
+    fingerprint: bool,
+    changed_mask: &str,
+) -> Map<String, Value> {
+    data.iter()
+        .map(|(key, value)| {
+            let masked = if fingerprint {
+                fingerprint_of(value)
+            } else {
+                match other.and_then(|o| o.get(key)) {
+                    Some(other_value) if other_value != value => changed_mask.to_string(),
+                    _ => MASK.to_string(),
+                }
+            };
+            (key.clone(), Value::String(masked))
+        })
+        .collect()
+}
+
+/// Short SHA-256 fingerprint of a value, e.g. `*** sha256:9f86d081`
+fn fingerprint_of(value: &Value) -> String {
+    format!("{} {}{}", MASK, DIGEST_PREFIX, &hex_digest(value)[..8])
+}
+
+/// Random salt for [`digest_secret_data`], hex encoded
+pub fn random_salt() -> anyhow::Result<String> {
+    let mut salt = [0u8; 16];
+    getrandom::getrandom(&mut salt)
+        .map_err(|e| anyhow::anyhow!("Failed to generate a salt: {}", e))?;
+    Ok(hex(&salt))
+}
+
+/// Hex encoded SHA-256 digest of a value, strings are hashed without quotes
+fn hex_digest(value: &Value) -> String {
+    salted_digest(value, "")
+}
+
+/// Hex encoded SHA-256 digest of `salt` followed by a value
+fn salted_digest(value: &Value, salt: &str) -> String {
+    let bytes = match value {
+        Value::String(s) => s.as_bytes().to_vec(),
+        other => other.to_string().into_bytes(),
+    };
+    hex(&Sha256::new()
+        .chain_update(salt.as_bytes())
+        .chain_update(&bytes)
+        .finalize())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn secret(data: Value) -> Value {
+        json!({"apiVersion": "v1", "kind": "Secret", "metadata": {"name": "s"}, "data": data})
+    }
+
+    #[test]
+    fn masks_unchanged_changed_added_and_removed_keys() {
+        let mut live = secret(json!({"same": "YQ==", "changed": "Yg==", "removed": "Yw=="}));
+        let mut local = secret(json!({"same": "YQ==", "changed": "ZA==", "added": "ZQ=="}));
+
+        mask_secret_data(Some(&mut live), &mut local, false);
+        assert_eq!(
+            live["data"],
+            json!({"same": MASK, "changed": MASK_BEFORE, "removed": MASK})
+        );
+        assert_eq!(
+            local["data"],
+            json!({"same": MASK, "changed": MASK_AFTER, "added": MASK})
+        );
+    }
+
+    #[test]
+    fn masks_string_data_and_new_secrets() {
+        let mut local = json!({
+            "apiVersion": "v1",
+            "kind": "Secret",
+            "stringData": {"password": "hunter2"},
+        });
+
+        mask_secret_data(None, &mut local, false);
+        assert_eq!(local["stringData"], json!({"password": MASK}));
+    }
+
+    #[test]
+    fn fingerprints_tell_values_apart() {
+        let mut live = secret(json!({"key": "YQ=="}));
+        let mut local = secret(json!({"key": "Yg=="}));
+
+        mask_secret_data(Some(&mut live), &mut local, true);
+        let (before, after) = (live["data"]["key"].clone(), local["data"]["key"].clone());
+        assert_ne!(before, after);
+        assert!(before.as_str().unwrap().starts_with("*** sha256:"));
+        assert!(!before.to_string().contains("YQ=="));
+    }
+
+    #[test]
+    fn leaves_other_resources_untouched() {
+        let original = json!({"apiVersion": "v1", "kind": "ConfigMap", "data": {"key": "value"}});
+        let mut local = original.clone();
+        mask_secret_data(None, &mut local, false);
+        assert_eq!(local, original);
+    }
+
+    #[test]
+    fn salted_digests_depend_on_the_salt() {
+        let mut a = secret(json!({"key": "YQ=="}));
+        let mut b = a.clone();
+        digest_secret_data(&mut a, "one");
+        digest_secret_data(&mut b, "two");
+        assert_ne!(a["data"]["key"], b["data"]["key"]);
+
+        // Digests are kept as they are
+        let digested = a.clone();
+        digest_secret_data(&mut a, "two");
+        assert_eq!(a, digested);
+    }
+}
@@ -0,0 +1,310 @@
+//! Selection of the resources of a target to diff.
+//!
+//! A [`ResourceSelector`] narrows a target down to the resources one cares
+//! about, e.g. the Deployment of a single microservice, by kind, name,
+//! namespace and labels. Names and namespaces are glob patterns, labels use
+//! the Kubernetes label selector syntax (`app=foo,tier!=db,env in (a,b)`).
+
+use std::{collections::BTreeMap, str::FromStr};
+
+use glob::Pattern;
+use serde_json::Value;
+
+/// Which resources of a target are diffed.
+///
+/// Empty lists select everything, exclusions win over inclusions.
+#[derive(Debug, Clone, Default)]
+pub struct ResourceSelector {
+    /// Only resources of these kinds, compared case-insensitively
+    pub kinds: Vec<String>,
+    /// Only resources whose name matches one of these globs
+    pub names: Vec<String>,
+    /// Only resources in a namespace matching one of these globs
+    pub namespaces: Vec<String>,
+    /// Only resources whose labels match this selector
+    pub labels: Option<LabelSelector>,
+    /// Skip resources of these kinds
+    pub exclude_kinds: Vec<String>,
+    /// Skip resources whose name matches one of these globs
+    pub exclude_names: Vec<String>,
+    /// Skip resources in a namespace matching one of these globs
+    pub exclude_namespaces: Vec<String>,
+}
+
+impl ResourceSelector {
+    /// Whether a resource with the given identifiers and labels is selected
+    pub fn matches(
+        &self,
+        kind: &str,
+        name: &str,
+        namespace: Option<&str>,
+        labels: &BTreeMap<String, String>,
+    ) -> bool {
+        let kind_matches = |kinds: &[String]| kinds.iter().any(|k| k.eq_ignore_ascii_case(kind));
+        let namespace_matches =
+            |patterns: &[String]| namespace.is_some_and(|ns| glob_matches(patterns, ns));
+
+        (self.kinds.is_empty() || kind_matches(&self.kinds))
+            && (self.names.is_empty() || glob_matches(&self.names, name))
+            && (self.namespaces.is_empty() || namespace_matches(&self.namespaces))
+            && self.labels.as_ref().is_none_or(|s| s.matches(labels))
+            && !kind_matches(&self.exclude_kinds)
+            && !glob_matches(&self.exclude_names, name)
+            && !namespace_matches(&self.exclude_namespaces)
+    }
+
+    /// Whether a manifest is selected, `default_namespace` applies to manifests without one
+    pub fn matches_manifest(&self, value: &Value, default_namespace: Option<&str>) -> bool {
+        let metadata = &value["metadata"];
+        let labels = metadata["labels"]
+            .as_object()
+            .map(|labels| {
+                labels
+                    .iter()
+                    .filter_map(|(k, v)| Some((k.clone(), v.as_str()?.to_string())))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        self.matches(
+            value["kind"].as_str().unwrap_or_default(),
+            metadata["name"].as_str().unwrap_or_default(),
+            metadata["namespace"].as_str().or(default_namespace),
+            &labels,
+        )
+    }
+}
+
+fn glob_matches(patterns: &[String], value: &str) -> bool {
+    patterns
+        .iter()
+        .any(|pattern| Pattern::new(pattern).is_ok_and(|pattern| pattern.matches(value)))
+}
+
+/// Parsed Kubernetes label selector, e.g. `app=foo,tier!=db,env in (a,b),!canary`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LabelSelector(Vec<Requirement>);
+
+/// A single comma-separated term of a label selector
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Requirement {
+    Equals(String, String),
+    NotEquals(String, String),
+    In(String, Vec<String>),
+    NotIn(String, Vec<String>),
+    Exists(String),
+    DoesNotExist(String),
+}
+
+impl LabelSelector {
+    /// Whether labels satisfy every requirement of the selector
+    pub fn matches(&self, labels: &BTreeMap<String, String>) -> bool {
+        self.0.iter().all(|requirement| match requirement {
+            Requirement::Equals(key, value) => labels.get(key) == Some(value),
+            Requirement::NotEquals(key, value) => labels.get(key) != Some(value),
+            Requirement::In(key, values) => labels.get(key).is_some_and(|v| values.contains(v)),
+            Requirement::NotIn(key, values) => labels.get(key).is_none_or(|v| !values.contains(v)),
+            Requirement::Exists(key) => labels.contains_key(key),
+            Requirement::DoesNotExist(key) => !labels.contains_key(key),
+        })
+    }
+}
+
+impl FromStr for LabelSelector {
+    type Err = anyhow::Error;
+
+    fn from_str(selector: &str) -> anyhow::Result<Self> {
+        split_terms(selector)?
+            .into_iter()
+            .filter(|term| !term.is_empty())
+            .map(parse_requirement)
+            .collect::<anyhow::Result<_>>()
+            .map(LabelSelector)
+    }
+}
+
+/// Split a selector at the commas that aren't part of a value set
+fn split_terms(selector: &str) -> anyhow::Result<Vec<&str>> {
+    let unbalanced = || anyhow::anyhow!("Unbalanced parentheses in label selector '{}'", selector);
+    let mut terms = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in selector.char_indices() {
+        match c {
+            '(' if depth == 0 => depth += 1,
+            ')' if depth == 1 => depth -= 1,
+            '(' | ')' => return Err(unbalanced()),
+            ',' if depth == 0 => {
+                terms.push(selector[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if depth != 0 {
+        return Err(unbalanced());
+    }
+    terms.push(selector[start..].trim());
+    Ok(terms)
+}
+
+fn parse_requirement(term: &str) -> anyhow::Result<Requirement> {
+    let invalid = || anyhow::anyhow!("Invalid label selector term '{}'", term);
+    let key = |key: &str| {
+        let key = key.trim();
+        if is_label_key(key) {
+            Ok(key.to_string())
+        } else {
+            Err(invalid())
+        }
+    };
+    let value = |value: &str| {
+        let value = value.trim();
+        if is_label_value(value) {
+            Ok(value.to_string())
+        } else {
+            Err(invalid())
+        }
+    };
+
+    if let Some(rest) = term.strip_prefix('!') {
+        return Ok(Requirement::DoesNotExist(key(rest)?));
+    }
+    if let Some((key_and_op, values)) = term.split_once('(') {
+        let values = values.strip_suffix(')').ok_or_else(invalid)?;
+        if values.trim().is_empty() {
+            return Err(invalid());
+        }
+        let values = values
+            .split(',')
+            .map(value)
+            .collect::<anyhow::Result<_>>()?;
+        return match key_and_op.split_whitespace().collect::<Vec<_>>()[..] {
+            [k, "in"] => Ok(Requirement::In(key(k)?, values)),
+            [k, "notin"] => Ok(Requirement::NotIn(key(k)?, values)),
+            _ => Err(invalid()),
+        };
+    }
+    if let Some((k, v)) = term.split_once("!=") {
+        return Ok(Requirement::NotEquals(key(k)?, value(v)?));
+    }
+    if let Some((k, v)) = term.split_once("==").or_else(|| term.split_once('=')) {
+        return Ok(Requirement::Equals(key(k)?, value(v)?));
+    }
+    Ok(Requirement::Exists(key(term)?))
+}
+
+/// Whether a label key is valid, a name with an optional DNS subdomain prefix
+fn is_label_key(key: &str) -> bool {
+    let (prefix, name) = match key.split_once('/') {
+        Some((prefix, name)) => (Some(prefix), name),
+        None => (None, key),
+    };
+    let prefix_valid = prefix.is_none_or(|prefix| {
+        !prefix.is_empty()
+            && prefix.len() <= 253
+            && prefix
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '.')
+    });
+    prefix_valid && !name.is_empty() && is_label_value(name)
+}
+
+/// Whether a label value is valid: empty, or up to 63 alphanumerics, `-`, `_`
+/// and `.` starting and ending with an alphanumeric
+fn is_label_value(value: &str) -> bool {
+    let alphanumeric = |c: Option<char>| c.is_none_or(|c| c.is_ascii_alphanumeric());
+    value.len() <= 63
+        && alphanumeric(value.chars().next())
+        && alphanumeric(value.chars().last())
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_every_kind_of_requirement() {
+        let selector: LabelSelector =
+            "app=web, tier!=db,env in (prod, staging),track notin (canary),release,!legacy"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            selector.0,
+            vec![
+                Requirement::Equals("app".to_string(), "web".to_string()),
+                Requirement::NotEquals("tier".to_string(), "db".to_string()),
+                Requirement::In(
+                    "env".to_string(),
+                    vec!["prod".to_string(), "staging".to_string()]
+                ),
+                Requirement::NotIn("track".to_string(), vec!["canary".to_string()]),
+                Requirement::Exists("release".to_string()),
+                Requirement::DoesNotExist("legacy".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn accepts_prefixed_keys_and_empty_values() {
+        let selector: LabelSelector = "app.kubernetes.io/name==web,empty=".parse().unwrap();
+        assert!(selector.matches(&labels(&[("app.kubernetes.io/name", "web"), ("empty", "")])));
+    }
+
+    #[test]
+    fn rejects_invalid_selectors() {
+        for selector in [
+            "a=b=c",
+            "k in ()",
+            "k in (a",
+            "k in a)",
+            "k in ((a))",
+            "k notin (a,b c)",
+            "k between (a)",
+            "a b",
+            "=b",
+            "!",
+            "/name=a",
+        ] {
+            assert!(
+                selector.parse::<LabelSelector>().is_err(),
+                "{} should be rejected",
+                selector
+            );
+        }
+    }
+
+    #[test]
+    fn matches_labels() {
+        let selector: LabelSelector = "app=web,env in (prod),!legacy".parse().unwrap();
+        assert!(selector.matches(&labels(&[("app", "web"), ("env", "prod")])));
+        assert!(!selector.matches(&labels(&[("app", "web"), ("env", "dev")])));
+        assert!(!selector.matches(&labels(&[
+            ("app", "web"),
+            ("env", "prod"),
+            ("legacy", "true")
+        ])));
+    }
+
+    #[test]
+    fn selects_manifests_in_the_default_namespace() {
+        let selector = ResourceSelector {
+            namespaces: vec!["team-*".to_string()],
+            ..ResourceSelector::default()
+        };
+        let manifest = serde_json::json!({"kind": "ConfigMap", "metadata": {"name": "a"}});
+        assert!(selector.matches_manifest(&manifest, Some("team-a")));
+        assert!(!selector.matches_manifest(&manifest, Some("default")));
+        assert!(!selector.matches_manifest(&manifest, None));
+    }
+}
@@ -1,13 +1,15 @@
 use glob::glob;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::io::{BufReader, Error};
 use std::path::Path;
 use std::{fs::File, io};
 
 use crate::enums::LogLevel;
+use crate::filter::IgnoreRule;
+use crate::helm::HelmTarget;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Configs {
     #[serde(default)]
     pub include: Vec<String>,
@@ -17,17 +19,45 @@ pub struct Configs {
     pub env: String,
     #[serde(default)]
     pub log: LogLevel,
+    #[serde(default)]
+    pub ignore: Vec<IgnoreRule>,
+    /// Kubeconfig context to use for each env
+    #[serde(default)]
+    pub contexts: HashMap<String, String>,
+    /// Rendering settings for Helm chart targets
+    #[serde(default)]
+    pub helm: Vec<HelmTarget>,
+    /// Field managers treated as ours in three-way and owned-only diffs
+    #[serde(default)]
+    pub field_managers: Vec<String>,
+    /// Limits, timeouts and retries of API requests
+    #[serde(default)]
+    pub requests: RequestSettings,
+    /// Seconds discovered resource types are cached, 0 disables the cache
+    #[serde(default)]
+    pub discovery_ttl: Option<u64>,
+}
+
+/// API request settings, unset values use the client's defaults
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RequestSettings {
+    /// Maximum number of API requests in flight at once
+    pub concurrency: Option<usize>,
+    /// Timeout of a single request in seconds, 0 disables it
+    pub timeout: Option<u64>,
+    /// How often throttled or failed requests are retried
+    pub retries: Option<u32>,
 }
 
 pub fn expanduser(path: &str) -> String {
-    if path.starts_with("~/") {
-        format!("{}/{}", dirs::home_dir().unwrap().display(), &path[2..])
+    if let Some(rest) = path.strip_prefix("~/") {
+        format!("{}/{}", dirs::home_dir().unwrap().display(), rest)
     } else {
         path.to_string()
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize)]
 pub struct Settings {
     pub configs: Configs,
 }
@@ -45,7 +75,8 @@ impl Settings {
         let settings_path = Path::new(&settings_path_str);
         let file = File::open(settings_path)?;
         let reader = BufReader::new(file);
-        let settings: Settings = serde_yaml::from_reader(reader).unwrap();
+        let settings: Settings = serde_yaml::from_reader(reader)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
 
         Ok(settings)
     }
@@ -54,11 +85,11 @@ impl Settings {
         let mut paths = HashSet::new();
         let env: String = self.configs.env.to_string();
         for inc in &self.configs.include {
-            let expanded = expanduser(&inc);
+            let expanded = expanduser(inc);
             for entry in glob(&expanded).expect("Failed to read glob pattern") {
                 match entry {
                     Ok(path) => {
-                        paths.insert(format!("{}/{}", path.display().to_string(), env));
+                        paths.insert(format!("{}/{}", path.display(), env));
                     }
                     Err(e) => {
                         println!("{:?}", e);
@@ -67,11 +98,11 @@ impl Settings {
             }
         }
         for exc in &self.configs.exclude {
-            let expanded = expanduser(&exc);
+            let expanded = expanduser(exc);
             for entry in glob(&expanded).expect("Failed to read glob pattern") {
                 match entry {
                     Ok(path) => {
-                        paths.remove(&format!("{}/{}", path.display().to_string(), env));
+                        paths.remove(&format!("{}/{}", path.display(), env));
                     }
                     Err(e) => {
                         println!("{:?}", e);
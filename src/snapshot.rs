@@ -0,0 +1,346 @@
+//! Snapshots of live cluster state on disk.
+//!
+//! A snapshot stores the filtered live objects of a set of targets as one
+//! YAML file per object, laid out as `<namespace>/<Kind>.<group>/<name>.yaml`
+//! with `_cluster` in place of the namespace for cluster-scoped objects.
+//! Snapshots can later be diffed against the rendered manifests without
+//! cluster access, or against the current live state.
+//!
+//! Secret values are stored as SHA-256 digests salted with a random value of
+//! the snapshot, unless `show_secrets` is set.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    commands::{Commands, ResourceDiff},
+    compare::{fetch_live, parse_documents},
+    error::KubediffError,
+    filter::filter_resource,
+    kube_client::KubeClient,
+    processor::{DiffOptions, DiffResult, TargetResult},
+    resource::ResourceId,
+    secrets::{digest_secret_data, random_salt},
+};
+
+/// File in the snapshot root describing where the snapshot was taken
+const INFO_FILE: &str = "snapshot.yaml";
+/// Directory used in place of a namespace for cluster-scoped objects
+const CLUSTER_SCOPE_DIR: &str = "_cluster";
+
+/// Where a snapshot was taken
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotInfo {
+    /// URL of the API server the objects were read from
+    pub cluster: String,
+    /// Namespace used for manifests that don't specify one
+    pub default_namespace: String,
+    /// Salt of the stored Secret digests, empty in snapshots taken without one
+    #[serde(default)]
+    pub secret_salt: String,
+}
+
+/// Objects of a target written by [`Snapshot::capture`]
+#[derive(Debug, Default)]
+pub struct Capture {
+    pub written: Vec<ResourceId>,
+    /// Resources whose live object couldn't be read or written
+    pub failed: Vec<(ResourceId, KubediffError)>,
+}
+
+/// A snapshot directory written by [`Snapshot::capture`]
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    dir: PathBuf,
+    pub info: SnapshotInfo,
+}
+
+impl Snapshot {
+    /// Start a snapshot of the cluster `client` talks to in `dir`.
+    ///
+    /// A snapshot already in `dir` is extended, keeping its salt so the
+    /// Secret digests of all its objects stay comparable. It must have been
+    /// taken of the same cluster.
+    pub fn create(client: &KubeClient, dir: &Path) -> anyhow::Result<Self> {
+        if dir.join(INFO_FILE).is_file() {
+            let snapshot = Self::open(dir)?;
+            if snapshot.info.cluster != client.cluster_url() {
+                return Err(anyhow!(
+                    "{} holds a snapshot of {}, not of {}",
+                    dir.display(),
+                    snapshot.info.cluster,
+                    client.cluster_url()
+                ));
+            }
+            return Ok(snapshot);
+        }
+
+        let info = SnapshotInfo {
+            cluster: client.cluster_url().to_string(),
+            default_namespace: client.default_namespace().to_string(),
+            secret_salt: random_salt()?,
+        };
+        fs::create_dir_all(dir)?;
+        fs::write(dir.join(INFO_FILE), serde_yaml::to_string(&info)?)?;
+        Ok(Snapshot {
+            dir: dir.to_path_buf(),
+            info,
+        })
+    }
+
+    /// Write the filtered live objects of a target's resources to the snapshot.
+    ///
+    /// Resources that don't exist in the cluster are skipped. Resources whose
+    /// live object can't be read or written are reported in the result, the
+    /// other objects are still written.
+    pub async fn capture(
+        &self,
+        client: &KubeClient,
+        target: &str,
+        options: &DiffOptions,
+    ) -> anyhow::Result<Capture> {
+        let build = Commands::get_build_with_options(target, &options.build)?;
+        let mut errors = vec![];
+        let (documents, _) = parse_documents(
+            target,
+            &build,
+            &options.selector,
+            client.default_namespace(),
+            &mut errors,
+        );
+        if let Some(error) = errors.into_iter().find_map(|e| e.error) {
+            return Err(anyhow!(
+                "Failed to parse manifests of {}: {}",
+                target,
+                error
+            ));
+        }
+
+        let futures: Vec<_> = documents
+            .keys()
+            .map(|id| async move { (id, fetch_live(client, id).await) })
+            .collect();
+        let mut capture = Capture::default();
+        for (id, live) in join_all(futures).await {
+            match live.and_then(|live| self.write(live, options)) {
+                Ok(Some(written)) => capture.written.push(written),
+                Ok(None) => {}
+                Err(e) => capture.failed.push((id.clone(), e.into())),
+            }
+        }
+        Ok(capture)
+    }
+
+    /// Write a filtered live object, None if it doesn't exist
+    fn write(
+        &self,
+        live: Option<Value>,
+        options: &DiffOptions,
+    ) -> anyhow::Result<Option<ResourceId>> {
+        let Some(mut live) = live else {
+            return Ok(None);
+        };
+        filter_resource(&mut live);
+        if !options.show_secrets {
+            digest_secret_data(&mut live, &self.info.secret_salt);
+        }
+        let id = ResourceId::from_value(&live)
+            .ok_or_else(|| anyhow!("Live object without apiVersion, kind or name"))?;
+
+        let path = self.dir.join(object_path(&id));
+        fs::create_dir_all(path.parent().unwrap_or(&self.dir))?;
+        fs::write(&path, serde_yaml::to_string(&live)?)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(Some(id))
+    }
+
+    /// Open a snapshot directory
+    pub fn open(dir: &Path) -> anyhow::Result<Self> {
+        let info_path = dir.join(INFO_FILE);
+        let info = fs::read_to_string(&info_path)
+            .with_context(|| format!("Not a snapshot directory: {}", dir.display()))?;
+        Ok(Snapshot {
+            dir: dir.to_path_buf(),
+            info: serde_yaml::from_str(&info)?,
+        })
+    }
+
+    /// Read the stored object of a resource, None if it isn't in the snapshot
+    ///
+    /// Resources without a namespace are looked up in the snapshot's default
+    /// namespace first and as cluster-scoped objects second.
+    pub fn get(&self, id: &ResourceId) -> anyhow::Result<Option<Value>> {
+        let mut candidates = vec![];
+        match &id.namespace {
+            Some(_) => candidates.push(id.clone()),
+            None => {
+                candidates.push(ResourceId {
+                    namespace: Some(self.info.default_namespace.clone()),
+                    ..id.clone()
+                });
+                candidates.push(id.clone());
+            }
+        }
+
+        for candidate in candidates {
+            let path = self.dir.join(object_path(&candidate));
+            if path.is_file() {
+                let content = fs::read_to_string(&path)?;
+                return Ok(Some(serde_yaml::from_str(&content)?));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Diff the rendered manifests of a target against the snapshot, without cluster access
+    ///
+    /// The manifests aren't normalized by a dry-run, so the stored objects
+    /// are projected onto the fields the manifests set.
+    pub fn diff_manifests(&self, target: &str, options: &DiffOptions) -> TargetResult {
+        let (documents, errors) = match build_documents(target, &self.info, options) {
+            Ok(built) => built,
+            Err(result) => return result,
+        };
+
+        let mut results: Vec<DiffResult> = documents
+            .into_iter()
+            .map(|(id, mut local)| {
+                self.default_namespace(&id, &mut local);
+                let diff = self.diff_resource(&id, Some(local), options, true);
+                DiffResult::for_resource(target, &id, diff)
+            })
+            .collect();
+        results.extend(errors);
+
+        TargetResult {
+            target: target.to_string(),
+            results,
+            build_error: None,
+        }
+    }
+
+    /// Diff the current live objects of a target's resources against the snapshot
+    pub async fn diff_live(
+        &self,
+        client: &KubeClient,
+        target: &str,
+        options: &DiffOptions,
+    ) -> TargetResult {
+        let (documents, errors) = match build_documents(target, &self.info, options) {
+            Ok(built) => built,
+            Err(result) => return result,
+        };
+
+        let futures: Vec<_> = documents
+            .keys()
+            .map(|id| async move {
+                let diff = match fetch_live(client, id).await {
+                    Ok(live) => self.diff_resource(id, live, options, false),
+                    Err(e) => Err(e),
+                };
+                DiffResult::for_resource(target, id, diff)
+            })
+            .collect();
+        let mut results = join_all(futures).await;
+        results.extend(errors);
+
+        TargetResult {
+            target: target.to_string(),
+            results,
+            build_error: None,
+        }
+    }
+
+    /// Set the snapshot's default namespace on a manifest without one, like the API server would
+    ///
+    /// Cluster-scoped objects are stored without a namespace and are left untouched.
+    fn default_namespace(&self, id: &ResourceId, local: &mut Value) {
+        if id.namespace.is_some() {
+            return;
+        }
+        let namespaced = ResourceId {
+            namespace: Some(self.info.default_namespace.clone()),
+            ..id.clone()
+        };
+        if self.dir.join(object_path(&namespaced)).is_file() {
+            if let Some(metadata) = local["metadata"].as_object_mut() {
+                metadata.insert(
+                    "namespace".to_string(),
+                    Value::String(self.info.default_namespace.clone()),
+                );
+            }
+        }
+    }
+
+    /// Diff the stored object of a resource against its current state
+    ///
+    /// With `client_side` the current state is a manifest, see
+    /// [`Commands::diff_resources_client_side`].
+    fn diff_resource(
+        &self,
+        id: &ResourceId,
+        mut current: Option<Value>,
+        options: &DiffOptions,
+        client_side: bool,
+    ) -> anyhow::Result<ResourceDiff> {
+        let mut stored = self.get(id)?;
+        if !options.show_secrets {
+            // Snapshots only hold digests of Secret values, compare digests on both sides
+            for value in stored.iter_mut().chain(current.iter_mut()) {
+                digest_secret_data(value, &self.info.secret_salt);
+            }
+        }
+        if client_side {
+            Commands::diff_resources_client_side(&id.to_string(), stored, current, options)
+        } else {
+            Commands::diff_resources(&id.to_string(), stored, current, options)
+        }
+    }
+}
+
+/// Build a target and parse its documents, keeping parse errors as results
+///
+/// Returns the failed target result if the build fails.
+fn build_documents(
+    target: &str,
+    info: &SnapshotInfo,
+    options: &DiffOptions,
+) -> Result<(BTreeMap<ResourceId, Value>, Vec<DiffResult>), TargetResult> {
+    let build =
+        Commands::get_build_with_options(target, &options.build).map_err(|e| TargetResult {
+            target: target.to_string(),
+            results: vec![],
+            build_error: Some(KubediffError::build_failed(e)),
+        })?;
+
+    let mut errors = vec![];
+    let (documents, _) = parse_documents(
+        target,
+        &build,
+        &options.selector,
+        &info.default_namespace,
+        &mut errors,
+    );
+    Ok((documents, errors))
+}
+
+/// Path of an object relative to the snapshot root
+fn object_path(id: &ResourceId) -> PathBuf {
+    let namespace = id.namespace.as_deref().unwrap_or(CLUSTER_SCOPE_DIR);
+    let kind = match id.group() {
+        "" => id.kind.clone(),
+        group => format!("{}.{}", id.kind, group),
+    };
+    Path::new(namespace)
+        .join(kind)
+        .join(format!("{}.yaml", id.name))
+}
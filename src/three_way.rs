@@ -0,0 +1,175 @@
+//! Three-way classification of changes.
+//!
+//! A two-way diff can't tell whether a field differs because the manifest
+//! changed or because someone edited the live object. Comparing both sides
+//! with what was last applied tells them apart:
+//!
+//! - With the `kubectl.kubernetes.io/last-applied-configuration` annotation,
+//!   a field whose live value still matches the last applied value was
+//!   changed locally, one whose local value matches it drifted live, and one
+//!   that matches neither changed on both sides.
+//! - Without it, the ownership sets in `metadata.managedFields` are used: a
+//!   field owned only by other field managers drifted, one owned by both our
+//!   and other managers is a conflict, anything else is a local change.
+
+use serde_json::Value;
+
+use crate::{
+    field_diff::{ChangeOrigin, FieldChange},
+    managed_fields::ManagedFields,
+};
+
+/// Annotation holding the manifest last applied with `kubectl apply`
+const LAST_APPLIED_ANNOTATION: &str = "kubectl.kubernetes.io/last-applied-configuration";
+
+/// What was last applied to a live object
+#[derive(Debug, Clone)]
+pub enum Base {
+    /// The manifest stored in the last-applied annotation
+    LastApplied(Value),
+    /// Fields owned by each field manager
+    ManagedFields(ManagedFields),
+}
+
+impl Base {
+    /// Read the base of an unfiltered live object, None if it has neither
+    /// a last-applied annotation nor managed fields
+    pub fn from_live(live: &Value) -> Option<Base> {
+        if let Some(applied) = live["metadata"]["annotations"][LAST_APPLIED_ANNOTATION].as_str() {
+            if let Ok(value) = serde_json::from_str(applied) {
+                return Some(Base::LastApplied(value));
+            }
+        }
+
+        ManagedFields::from_object(live).map(Base::ManagedFields)
+    }
+
+    /// Classify a change between the filtered, unmasked live and local values
+    ///
+    /// `managers` are the field managers treated as ours, empty for the defaults.
+    pub fn classify(
+        &self,
+        change: &FieldChange,
+        live: &Value,
+        local: &Value,
+        managers: &[String],
+    ) -> ChangeOrigin {
+        match self {
+            Base::LastApplied(applied) => {
+                let base = change.path.get(applied);
+                if base == change.path.get(live) {
+                    ChangeOrigin::Local
+                } else if base == change.path.get(local) {
+                    ChangeOrigin::Drift
+                } else {
+                    ChangeOrigin::Conflict
+                }
+            }
+            Base::ManagedFields(managed) => {
+                let (ours, others) = managed.owners_of(&change.path, managers);
+                match (ours, others) {
+                    (true, true) => ChangeOrigin::Conflict,
+                    (false, true) => ChangeOrigin::Drift,
+                    _ => ChangeOrigin::Local,
+                }
+            }
+        }
+    }
+}
+
+/// Set the origin of every change
+pub fn classify_changes(
+    changes: &mut [FieldChange],
+    base: &Base,
+    live: &Value,
+    local: &Value,
+    managers: &[String],
+) {
+    for change in changes {
+        change.origin = Some(base.classify(change, live, local, managers));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::field_diff::diff_values;
+
+    /// Classify the changes between live and local by path
+    fn origins(
+        base: &Base,
+        live: &Value,
+        local: &Value,
+        managers: &[String],
+    ) -> Vec<(String, ChangeOrigin)> {
+        let mut changes = diff_values(live, local);
+        classify_changes(&mut changes, base, live, local, managers);
+        changes
+            .into_iter()
+            .map(|c| (c.path.to_string(), c.origin.unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn classifies_against_the_last_applied_configuration() {
+        let applied = json!({"spec": {"replicas": 2, "paused": false, "minReadySeconds": 1}});
+        let live = json!({
+            "metadata": {"annotations": {LAST_APPLIED_ANNOTATION: applied.to_string()}},
+            "spec": {"replicas": 2, "paused": true, "minReadySeconds": 5}
+        });
+        let base = Base::from_live(&live).unwrap();
+        assert!(matches!(base, Base::LastApplied(_)));
+
+        let live = json!({"spec": {"replicas": 2, "paused": true, "minReadySeconds": 5}});
+        let local = json!({"spec": {"replicas": 3, "paused": false, "minReadySeconds": 7}});
+        assert_eq!(
+            origins(&base, &live, &local, &[]),
+            vec![
+                ("spec.minReadySeconds".to_string(), ChangeOrigin::Conflict),
+                ("spec.paused".to_string(), ChangeOrigin::Drift),
+                ("spec.replicas".to_string(), ChangeOrigin::Local),
+            ]
+        );
+    }
+
+    #[test]
+    fn classifies_by_managed_fields_without_last_applied() {
+        let live = json!({
+            "metadata": {"managedFields": [
+                {"manager": "kubectl", "fieldsV1": {"f:spec": {"f:paused": {}}}},
+                {"manager": "hpa", "fieldsV1": {"f:spec": {"f:replicas": {}}}},
+                {"manager": "editor", "fieldsV1": {"f:spec": {"f:paused": {}}}}
+            ]}
+        });
+        let base = Base::from_live(&live).unwrap();
+        assert!(matches!(base, Base::ManagedFields(_)));
+
+        let live = json!({"spec": {"replicas": 5, "paused": true, "minReadySeconds": 1}});
+        let local = json!({"spec": {"replicas": 3, "paused": false, "minReadySeconds": 7}});
+        assert_eq!(
+            origins(&base, &live, &local, &[]),
+            vec![
+                ("spec.minReadySeconds".to_string(), ChangeOrigin::Local),
+                ("spec.paused".to_string(), ChangeOrigin::Conflict),
+                ("spec.replicas".to_string(), ChangeOrigin::Drift),
+            ]
+        );
+
+        // A configured manager makes the fields it owns ours
+        let managers = ["hpa".to_string()];
+        assert_eq!(
+            origins(&base, &live, &local, &managers)[2],
+            ("spec.replicas".to_string(), ChangeOrigin::Local)
+        );
+    }
+
+    #[test]
+    fn objects_without_a_base_have_none() {
+        assert!(Base::from_live(&json!({"metadata": {"name": "web"}})).is_none());
+        // An unparseable annotation falls back to the managed fields
+        let live = json!({"metadata": {"annotations": {LAST_APPLIED_ANNOTATION: "{"}}});
+        assert!(Base::from_live(&live).is_none());
+    }
+}